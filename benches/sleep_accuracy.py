@@ -0,0 +1,110 @@
+"""
+Benchmark of how accurate blocking sleeps are. Each round sleeps repeatedly
+with random delays using rust_sleep and rust_sleep_precise with a few
+different slacks, and reports how late the sleeps completed:
+
+    cargo build --release
+    python benches/sleep_accuracy.py --lib target/release/libasync_python_ffi.so
+
+With --check, it fails if the precise sleeps are later than expected on an
+otherwise idle machine.
+"""
+
+import argparse
+import random
+import sys
+import time
+from pathlib import Path
+
+ROOT = Path(__file__).parent.parent
+sys.path.insert(0, str(ROOT))
+
+from ffilib import cdll_with_spec  # noqa: E402
+
+
+# Overshoots of the precise sleeps that --check allows, in nanoseconds. The
+# highest percentiles are left alone since they depend on the thread being
+# preempted.
+MAX_PRECISE_OVERSHOOT_NS = {0.5: 200_000, 0.9: 1_000_000}
+
+
+def percentile(values, p):
+    return values[min(int(len(values) * p), len(values) - 1)]
+
+
+def run(sleep, samples, min_delay_ms, max_delay_ms):
+    """
+    Call sleep with delays in nanoseconds and return the sorted overshoots
+    """
+    overshoots = []
+    for _ in range(samples):
+        delay_ns = random.randint(min_delay_ms, max_delay_ms) * 1_000_000
+        start = time.monotonic_ns()
+        status = sleep(delay_ns)
+        overshoots.append(time.monotonic_ns() - start - delay_ns)
+        if status != 0:
+            raise RuntimeError(f"sleep failed with status {status}")
+    overshoots.sort()
+    return overshoots
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.split("\n\n")[0])
+    parser.add_argument(
+        "--lib",
+        default=ROOT / "libasync_python_ffi.so",
+        help="library to benchmark",
+    )
+    parser.add_argument("--samples", type=int, default=500)
+    parser.add_argument(
+        "--slacks",
+        default="100,500,1000,2000",
+        help="comma separated slacks of the precise sleeps in µs",
+    )
+    parser.add_argument("--min-delay", type=int, default=1, help="ms")
+    parser.add_argument("--max-delay", type=int, default=5, help="ms")
+    parser.add_argument(
+        "--check",
+        action="store_true",
+        help="exit with an error if the precise sleeps are too late",
+    )
+    args = parser.parse_args()
+
+    lib = cdll_with_spec(args.lib, manifest="rust_ffi_manifest")
+
+    # rust_sleep only takes whole milliseconds, so the random delays are too
+    modes = [("rust_sleep", lambda ns: lib.rust_sleep(ns // 1_000_000))]
+    for slack_us in map(int, args.slacks.split(",")):
+        modes.append(
+            (
+                f"precise {slack_us}µs",
+                lambda ns, slack=slack_us * 1000: lib.rust_sleep_precise(ns, slack),
+            )
+        )
+
+    print(
+        f"{'mode':>16} {'p50 µs':>8} {'p90 µs':>8} {'p99 µs':>8} "
+        f"{'p99.9 µs':>9} {'max µs':>8}"
+    )
+    failed = False
+    for name, sleep in modes:
+        overshoots = run(sleep, args.samples, args.min_delay, args.max_delay)
+        if name.startswith("precise"):
+            failed |= overshoots[0] < 0 or any(
+                percentile(overshoots, p) >= limit
+                for p, limit in MAX_PRECISE_OVERSHOOT_NS.items()
+            )
+        print(
+            f"{name:>16} "
+            f"{percentile(overshoots, 0.5) / 1000:>8.1f} "
+            f"{percentile(overshoots, 0.9) / 1000:>8.1f} "
+            f"{percentile(overshoots, 0.99) / 1000:>8.1f} "
+            f"{percentile(overshoots, 0.999) / 1000:>9.1f} "
+            f"{overshoots[-1] / 1000:>8.1f}"
+        )
+    if args.check and failed:
+        sys.exit("precise sleeps completed early or later than expected")
+
+
+if __name__ == "__main__":
+    main()
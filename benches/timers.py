@@ -0,0 +1,116 @@
+"""
+Benchmark of many concurrent sleeps. Each round starts N sleeps with random
+delays and waits for all of them using the completion queue, reporting how
+late they completed and how many threads the process used. Build the library
+in release mode first for meaningful numbers:
+
+    cargo build --release
+    cargo run --release --bin gen
+    python benches/timers.py --lib target/release/libasync_python_ffi.so
+"""
+
+import argparse
+import os
+import random
+import select
+import statistics
+import sys
+import time
+from pathlib import Path
+
+ROOT = Path(__file__).parent.parent
+sys.path.insert(0, str(ROOT))
+
+from async_python_ffi import RustCompletion  # noqa: E402
+from ffilib import cdll_with_spec  # noqa: E402
+
+
+def thread_count():
+    return len(os.listdir("/proc/self/task"))
+
+
+def percentile(values, p):
+    return values[min(int(len(values) * p), len(values) - 1)]
+
+
+def run(lib, count, min_delay_ms, max_delay_ms):
+    fd = lib.rust_cq_fd()
+    buf = (RustCompletion * 1024)()
+    deadlines = {}
+    ops = []
+
+    start = time.monotonic()
+    for _ in range(count):
+        delay_ms = random.randint(min_delay_ms, max_delay_ms)
+        deadline = time.monotonic() + delay_ms / 1000
+        op = lib.rust_sleep_submit(None, delay_ms)
+        deadlines[lib.rust_op_id(op)] = deadline
+        ops.append(op)
+    submit_time = time.monotonic() - start
+    threads = thread_count()
+
+    lateness = []
+    while len(lateness) < count:
+        select.select([fd], [], [])
+        now = time.monotonic()
+        n = lib.rust_cq_drain(buf, len(buf))
+        for record in buf[:n]:
+            if record.status != 0:
+                raise RuntimeError(f"sleep failed with status {record.status}")
+            lateness.append(now - deadlines[record.op_id])
+
+    for op in ops:
+        lib.rust_op_free(op)
+
+    lateness.sort()
+    return {
+        "submit_rate": count / submit_time,
+        "threads": threads,
+        "p50": percentile(lateness, 0.5),
+        "p99": percentile(lateness, 0.99),
+        "max": lateness[-1],
+        "mean": statistics.fmean(lateness),
+    }
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.split("\n\n")[0])
+    parser.add_argument(
+        "--lib",
+        default=ROOT / "libasync_python_ffi.so",
+        help="library to benchmark",
+    )
+    parser.add_argument(
+        "--counts",
+        default="1000,10000,100000",
+        help="comma separated numbers of concurrent sleeps",
+    )
+    parser.add_argument("--min-delay", type=int, default=500, help="ms")
+    parser.add_argument("--max-delay", type=int, default=1500, help="ms")
+    args = parser.parse_args()
+
+    # The library describes itself, so it may have been built with other
+    # features than the generated bindings
+    lib = cdll_with_spec(args.lib, manifest="rust_ffi_manifest")
+
+    # Start the library's threads before counting them
+    op = lib.rust_sleep_start(None, 1)
+    lib.rust_op_wait(op)
+    lib.rust_op_free(op)
+    print(
+        f"{'sleeps':>8} {'submit/s':>10} {'threads':>8} "
+        f"{'p50 ms':>8} {'p99 ms':>8} {'max ms':>8}"
+    )
+    for count in map(int, args.counts.split(",")):
+        result = run(lib, count, args.min_delay, args.max_delay)
+        print(
+            f"{count:>8} {result['submit_rate']:>10.0f} "
+            f"{result['threads']:>8} "
+            f"{result['p50'] * 1000:>8.2f} {result['p99'] * 1000:>8.2f} "
+            f"{result['max'] * 1000:>8.2f}"
+        )
+    lib.rust_pool_shutdown()
+
+
+if __name__ == "__main__":
+    main()
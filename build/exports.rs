@@ -0,0 +1,399 @@
+//! Collect the library's C ABI from its source code. Only the parts that are
+//! compiled with the enabled features are collected.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_python_ffi_codegen::{async_ffi_exports, fn_args, result_value};
+use syn::punctuated::Punctuated;
+use syn::{
+    Attribute, Expr, ExprLit, ExprUnary, Fields, Item, ItemFn, Lit, LitStr, Meta, ReturnType,
+    Token, Type, UnOp, Visibility,
+};
+
+/// How an exported function behaves with regards to the calling thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Immediate,
+    /// Returns an operation handle.
+    Async,
+    /// Marked with `#[blocking]`.
+    Blocking,
+}
+
+pub struct Function {
+    pub name: String,
+    pub args: Vec<(String, Type)>,
+    pub ret: Option<Type>,
+    pub kind: Kind,
+    pub docs: Vec<String>,
+    /// The `#[cfg]` attributes of the function and its modules.
+    pub cfgs: Vec<Attribute>,
+    /// Generated by `#[async_ffi]` rather than defined in the source.
+    pub generated: bool,
+}
+
+impl Function {
+    /// Whether the function is compiled with the enabled features.
+    pub fn is_enabled(&self) -> bool {
+        is_enabled(&self.cfgs)
+    }
+}
+
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<(String, Type)>,
+    pub docs: Vec<String>,
+}
+
+pub struct Enum {
+    pub name: String,
+    pub variants: Vec<(String, i64)>,
+    pub docs: Vec<String>,
+}
+
+/// Function pointer type alias, like `pub type Callback = extern "C" fn(..)`.
+pub struct Callback {
+    pub name: String,
+    pub args: Vec<Type>,
+    pub ret: Option<Type>,
+}
+
+#[derive(Default)]
+pub struct Exports {
+    /// Public integer constants, like status codes.
+    pub constants: Vec<(String, i64)>,
+    pub functions: Vec<Function>,
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+    pub callbacks: Vec<Callback>,
+}
+
+impl Exports {
+    /// Collect the exports of the crate rooted at the given file. Functions
+    /// behind disabled features are collected too, everything else is only
+    /// collected if it's enabled.
+    pub fn collect(root: &Path) -> Self {
+        let mut exports = Self::default();
+        exports.collect_file(root, &root.with_file_name(""), &[]);
+        exports
+    }
+
+    /// Return the functions that are compiled with the enabled features.
+    pub fn enabled_functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.iter().filter(|f| f.is_enabled())
+    }
+
+    fn collect_file(&mut self, path: &Path, mod_dir: &Path, cfgs: &[Attribute]) {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let source = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+        let file = syn::parse_file(&source)
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", path.display(), e));
+        self.collect_items(&file.items, mod_dir, cfgs);
+    }
+
+    fn collect_items(&mut self, items: &[Item], mod_dir: &Path, cfgs: &[Attribute]) {
+        let enabled = |attrs: &[Attribute]| is_enabled(cfgs) && is_enabled(attrs);
+        for item in items {
+            match item {
+                Item::Mod(item) => {
+                    let dir = mod_dir.join(item.ident.to_string());
+                    let cfgs = with_cfgs(cfgs, &item.attrs);
+                    match &item.content {
+                        Some((_, items)) => self.collect_items(items, &dir, &cfgs),
+                        None => self.collect_file(&module_path(mod_dir, &item.ident), &dir, &cfgs),
+                    }
+                }
+                Item::Fn(item) => self.collect_fn(item, with_cfgs(cfgs, &item.attrs)),
+                Item::Const(item) if enabled(&item.attrs) && is_public(&item.vis) => {
+                    if let Some(value) = int_value(&item.expr) {
+                        self.constants.push((item.ident.to_string(), value));
+                    }
+                }
+                Item::Struct(item) if enabled(&item.attrs) && is_repr_c(&item.attrs) => {
+                    let fields = match &item.fields {
+                        Fields::Named(fields) => fields
+                            .named
+                            .iter()
+                            .map(|f| (f.ident.as_ref().unwrap().to_string(), f.ty.clone()))
+                            .collect(),
+                        _ => panic!("exported struct {} must have named fields", item.ident),
+                    };
+                    self.structs.push(Struct {
+                        name: item.ident.to_string(),
+                        fields,
+                        docs: docs(&item.attrs),
+                    });
+                }
+                Item::Enum(item) if enabled(&item.attrs) && is_repr_c(&item.attrs) => {
+                    let variants = item
+                        .variants
+                        .iter()
+                        .map(
+                            |v| match v.discriminant.as_ref().and_then(|(_, e)| int_value(e)) {
+                                Some(value) => (v.ident.to_string(), value),
+                                None => panic!("variant {} must have an explicit value", v.ident),
+                            },
+                        )
+                        .collect();
+                    self.enums.push(Enum {
+                        name: item.ident.to_string(),
+                        variants,
+                        docs: docs(&item.attrs),
+                    });
+                }
+                Item::Type(item) if enabled(&item.attrs) => {
+                    if let Type::BareFn(func) = non_null_type(&item.ty) {
+                        if func.abi.is_some() {
+                            self.callbacks.push(Callback {
+                                name: item.ident.to_string(),
+                                args: func.inputs.iter().map(|a| a.ty.clone()).collect(),
+                                ret: return_type(&func.output),
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn collect_fn(&mut self, item: &ItemFn, cfgs: Vec<Attribute>) {
+        let name = item.sig.ident.to_string();
+        let args = fn_args(&item.sig).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let docs = docs(&item.attrs);
+
+        if has_attr(&item.attrs, "no_mangle") && item.sig.abi.is_some() {
+            let ret = return_type(&item.sig.output);
+            let kind = if ret.as_ref().is_some_and(is_op_handle) {
+                Kind::Async
+            } else if has_attr(&item.attrs, "blocking") {
+                Kind::Blocking
+            } else {
+                Kind::Immediate
+            };
+            self.functions.push(Function {
+                name,
+                args,
+                ret,
+                kind,
+                docs,
+                cfgs,
+                generated: false,
+            });
+        } else if let Some(attr) = find_attr(&item.attrs, "async_ffi") {
+            let prefix = async_ffi_name(attr).unwrap_or_else(|| name.clone());
+            let value = result_value(&item.sig.output)
+                .unwrap_or_else(|e| panic!("{}: {}", name, e))
+                .cloned();
+            self.push_async_ffi(&name, &prefix, args, value, cfgs);
+        }
+    }
+
+    /// Add the functions generated by `#[async_ffi]`, including their
+    /// documentation.
+    fn push_async_ffi(
+        &mut self,
+        ident: &str,
+        prefix: &str,
+        args: Vec<(String, Type)>,
+        value: Option<Type>,
+        cfgs: Vec<Attribute>,
+    ) {
+        for export in async_ffi_exports(ident, prefix, args, value.as_ref()) {
+            let kind = if is_op_handle(&export.ret) {
+                Kind::Async
+            } else {
+                Kind::Immediate
+            };
+            self.functions.push(Function {
+                name: export.name,
+                args: export.args,
+                ret: Some(export.ret),
+                kind,
+                docs: export.docs,
+                cfgs: cfgs.clone(),
+                generated: true,
+            });
+        }
+    }
+}
+
+/// Return the `#[cfg]` attributes of an item appended to those of its parent.
+fn with_cfgs(parent: &[Attribute], attrs: &[Attribute]) -> Vec<Attribute> {
+    let mut cfgs = parent.to_vec();
+    cfgs.extend(
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .cloned(),
+    );
+    cfgs
+}
+
+fn module_path(dir: &Path, ident: &syn::Ident) -> PathBuf {
+    let file = dir.join(format!("{}.rs", ident));
+    if file.exists() {
+        file
+    } else {
+        dir.join(ident.to_string()).join("mod.rs")
+    }
+}
+
+fn is_public(vis: &Visibility) -> bool {
+    matches!(vis, Visibility::Public(_))
+}
+
+/// Evaluate an integer literal, which may be negated.
+fn int_value(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(i), ..
+        }) => i.base10_parse().ok(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => int_value(expr).map(|v| -v),
+        _ => None,
+    }
+}
+
+fn is_op_handle(ty: &Type) -> bool {
+    match ty {
+        Type::Ptr(ptr) => {
+            ptr.mutability.is_some() && type_name(&ptr.elem).as_deref() == Some("RustOp")
+        }
+        _ => false,
+    }
+}
+
+/// Return the last segment of a type's path, like `c_int` for
+/// `std::os::raw::c_int`.
+pub fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => Some(path.path.segments.last()?.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Return the function pointer type of a nullable one, like `fn()` for
+/// `Option<fn()>`, since C and ctypes don't tell the two apart.
+fn non_null_type(ty: &Type) -> &Type {
+    if let Type::Path(path) = ty {
+        let last = path.path.segments.last();
+        if let Some(segment) = last.filter(|segment| segment.ident == "Option") {
+            if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let Some(syn::GenericArgument::Type(inner)) = args.args.first() {
+                    return inner;
+                }
+            }
+        }
+    }
+    ty
+}
+
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    find_attr(attrs, name).is_some()
+}
+
+fn find_attr<'a>(attrs: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attrs
+        .iter()
+        .find(|attr| attr.path().segments.last().is_some_and(|s| s.ident == name))
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("repr")
+            && attr
+                .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .is_ok_and(|reprs| reprs.iter().any(|r| r.path().is_ident("C")))
+    })
+}
+
+/// Evaluate the `#[cfg(..)]` attributes against the enabled features. Only
+/// feature predicates are supported.
+fn is_enabled(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cfg"))
+        .all(|attr| eval_cfg(&attr.parse_args().expect("invalid cfg attribute")))
+}
+
+fn eval_cfg(meta: &Meta) -> bool {
+    let nested = |meta: &Meta| {
+        meta.require_list()
+            .unwrap()
+            .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .unwrap()
+    };
+    if meta.path().is_ident("feature") {
+        let value = &meta.require_name_value().unwrap().value;
+        match value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(feature),
+                ..
+            }) => {
+                let var = format!(
+                    "CARGO_FEATURE_{}",
+                    feature.value().to_uppercase().replace('-', "_")
+                );
+                env::var_os(var).is_some()
+            }
+            _ => panic!("invalid feature predicate"),
+        }
+    } else if meta.path().is_ident("not") {
+        !nested(meta).iter().all(eval_cfg)
+    } else if meta.path().is_ident("all") {
+        nested(meta).iter().all(eval_cfg)
+    } else if meta.path().is_ident("any") {
+        nested(meta).iter().any(eval_cfg)
+    } else {
+        panic!("unsupported cfg predicate")
+    }
+}
+
+fn docs(attrs: &[Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(doc), ..
+                }) => Some(
+                    doc.value()
+                        .strip_prefix(' ')
+                        .unwrap_or(&doc.value())
+                        .to_owned(),
+                ),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn async_ffi_name(attr: &Attribute) -> Option<String> {
+    let mut name = None;
+    if let Meta::List(_) = &attr.meta {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+            }
+            Ok(())
+        })
+        .expect("invalid async_ffi attribute");
+    }
+    name
+}
+
+fn return_type(output: &ReturnType) -> Option<Type> {
+    match output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some((**ty).clone()),
+    }
+}
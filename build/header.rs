@@ -0,0 +1,56 @@
+//! Generate the C header using cbindgen. cbindgen reads the crate's source, so
+//! it can't see the functions generated by `#[async_ffi]`. Those are declared
+//! in a separate source file that is passed to it along with the crate.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use quote::ToTokens;
+
+use crate::exports::Exports;
+
+/// Generate Rust declarations of the functions generated by `#[async_ffi]`.
+/// Functions behind features are included along with their `#[cfg]`
+/// attributes, which cbindgen turns into preprocessor conditionals.
+pub fn generated_functions(exports: &Exports) -> String {
+    let mut out = String::new();
+    for f in exports.functions.iter().filter(|f| f.generated) {
+        for line in &f.docs {
+            writeln!(out, "///{}{}", if line.is_empty() { "" } else { " " }, line).unwrap();
+        }
+        for cfg in &f.cfgs {
+            writeln!(out, "{}", cfg.to_token_stream()).unwrap();
+        }
+        let args: Vec<_> = f
+            .args
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty.to_token_stream()))
+            .collect();
+        write!(
+            out,
+            "#[no_mangle]\npub extern \"C\" fn {}({})",
+            f.name,
+            args.join(", ")
+        )
+        .unwrap();
+        if let Some(ret) = &f.ret {
+            write!(out, " -> {}", ret.to_token_stream()).unwrap();
+        }
+        out.push_str(" {}\n\n");
+    }
+    out
+}
+
+/// Generate the header for the crate in `crate_dir` and write it to `path`.
+/// `generated` is the path of the source written by `generated_functions`.
+pub fn write(crate_dir: &Path, generated: &Path, path: &Path) {
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("failed to read cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_crate(crate_dir)
+        .with_src(generated)
+        .generate()
+        .expect("failed to generate the C header")
+        .write_to_file(path);
+}
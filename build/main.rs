@@ -0,0 +1,69 @@
+//! Generate Python bindings, the C header and the manifest from the library's
+//! exported functions, so they can't drift from the Rust signatures.
+
+mod exports;
+mod header;
+mod manifest;
+mod python;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use exports::Exports;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let exports = Exports::collect(&root.join("src").join("lib.rs"));
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    write(out_dir.join("manifest.rs"), manifest::functions(&exports));
+
+    // The bindings are only written to OUT_DIR, since they depend on the
+    // features of the build. The gen binary copies them to the crate root
+    write(
+        out_dir.join("async_python_ffi.py"),
+        python::module(&exports),
+    );
+    write(out_dir.join("async_python_ffi.pyi"), python::stub(&exports));
+
+    let generated = out_dir.join("async_ffi.rs");
+    write(generated.clone(), header::generated_functions(&exports));
+    header::write(&root, &generated, &out_dir.join("async_python_ffi.h"));
+
+    // OUT_DIR is a few levels below the directory the library is written to
+    let lib_dir = out_dir.ancestors().nth(3).unwrap();
+    write(
+        out_dir.join("async_python_ffi.pc"),
+        pkg_config(&root, lib_dir),
+    );
+}
+
+/// Generate a pkg-config file for using the library from the build tree.
+fn pkg_config(root: &Path, lib_dir: &Path) -> String {
+    format!(
+        "prefix={}\n\
+         libdir={}\n\
+         includedir=${{prefix}}\n\
+         \n\
+         Name: async-python-ffi\n\
+         Description: Cancellable async operations over the C ABI\n\
+         Version: {}\n\
+         Cflags: -I${{includedir}}\n\
+         Libs: -L${{libdir}} -lasync_python_ffi\n\
+         Libs.private: -lgcc_s -lutil -lrt -lpthread -lm -ldl -lc\n",
+        root.display(),
+        lib_dir.display(),
+        env::var("CARGO_PKG_VERSION").unwrap(),
+    )
+}
+
+/// Write the file unless it's unchanged, to avoid touching its mtime.
+fn write(path: PathBuf, contents: String) {
+    if fs::read_to_string(&path).ok().as_deref() != Some(&contents) {
+        fs::write(&path, contents)
+            .unwrap_or_else(|e| panic!("failed to write {}: {}", path.display(), e));
+    }
+}
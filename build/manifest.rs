@@ -0,0 +1,65 @@
+//! Generate the table of exported functions returned by `rust_ffi_manifest`.
+
+use std::fmt::Write;
+
+use syn::Type;
+
+use crate::exports::{type_name, Exports, Kind};
+
+/// Generate the definition of `FUNCTIONS`, which is included by
+/// `src/manifest.rs`.
+pub fn functions(exports: &Exports) -> String {
+    let mut out = format!(
+        "static FUNCTIONS: [RustFfiFunction; {}] = [\n",
+        exports.enabled_functions().count()
+    );
+    for f in exports.enabled_functions() {
+        let args: Vec<_> = f
+            .args
+            .iter()
+            .map(|(_, ty)| format!("RustFfiType::{}", ffi_type(Some(ty), exports)))
+            .collect();
+        let kind = match f.kind {
+            Kind::Immediate => "Immediate",
+            Kind::Async => "Async",
+            Kind::Blocking => "Blocking",
+        };
+        writeln!(
+            out,
+            "    RustFfiFunction {{ name: c{:?}.as_ptr(), arg_types: [{}].as_ptr(), \
+             arg_count: {}, return_type: RustFfiType::{}, kind: RustFfiKind::{} }},",
+            f.name,
+            args.join(", "),
+            args.len(),
+            ffi_type(f.ret.as_ref(), exports),
+            kind,
+        )
+        .unwrap();
+    }
+    out.push_str("];\n");
+    out
+}
+
+/// Map a Rust type to the name of its `RustFfiType` variant.
+fn ffi_type(ty: Option<&Type>, exports: &Exports) -> &'static str {
+    let ty = match ty {
+        Some(ty) => ty,
+        None => return "Void",
+    };
+    let name = type_name(ty).unwrap_or_default();
+    match ty {
+        Type::Ptr(ptr) if type_name(&ptr.elem).as_deref() == Some("c_char") => "String",
+        Type::Ptr(_) => "Pointer",
+        _ => match name.as_str() {
+            "c_int" | "i32" => "Int",
+            "i64" => "Int64",
+            "u32" => "Uint32",
+            "u64" => "Uint64",
+            "usize" => "Size",
+            "bool" => "Bool",
+            _ if exports.callbacks.iter().any(|c| c.name == name) => "Callback",
+            _ if exports.enums.iter().any(|e| e.name == name) => "Int",
+            _ => panic!("type {} can't be described in the manifest", name),
+        },
+    }
+}
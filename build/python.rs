@@ -0,0 +1,313 @@
+//! Generate ctypes bindings for the collected exports. The module contains the
+//! spec class consumed by `cdll_with_spec` and the `.pyi` stub describes the
+//! functions as they are called from Python.
+
+use std::fmt::Write;
+
+use syn::Type;
+
+use crate::exports::{type_name, Exports, Function, Struct};
+
+const HEADER: &str = "# Generated by build.rs from the library's source code, do not edit\n";
+
+/// Generate the Python module with the spec class `AsyncPythonFfi`.
+pub fn module(exports: &Exports) -> String {
+    let mut out = String::from(HEADER);
+    out.push_str("import ctypes\nimport enum\n");
+
+    if !exports.constants.is_empty() {
+        out.push('\n');
+    }
+    for (name, value) in &exports.constants {
+        write!(out, "\n{} = {}", name, value).unwrap();
+    }
+    if !exports.constants.is_empty() {
+        out.push('\n');
+    }
+
+    for e in &exports.enums {
+        write!(out, "\n\nclass {}(enum.IntEnum):\n", e.name).unwrap();
+        if !e.docs.is_empty() {
+            docstring(&mut out, "    ", &e.docs);
+        }
+        for (name, value) in &e.variants {
+            writeln!(out, "    {} = {}", constant_name(name), value).unwrap();
+        }
+    }
+
+    for s in structs_in_order(exports) {
+        write!(out, "\n\nclass {}(ctypes.Structure):\n", s.name).unwrap();
+        if !s.docs.is_empty() {
+            docstring(&mut out, "    ", &s.docs);
+        }
+        out.push_str("    _fields_ = [\n");
+        for (name, ty) in &s.fields {
+            writeln!(out, "        ({:?}, {}),", name, ctype(ty, exports)).unwrap();
+        }
+        out.push_str("    ]\n");
+        if has_struct_size(s) {
+            out.push_str(
+                "\n    def __init__(self, *args, **kwargs):\n        \
+                 super().__init__(ctypes.sizeof(type(self)), *args, **kwargs)\n",
+            );
+        }
+    }
+
+    if !exports.callbacks.is_empty() {
+        out.push('\n');
+    }
+    for c in &exports.callbacks {
+        let mut types = vec![c
+            .ret
+            .as_ref()
+            .map_or_else(|| "None".to_owned(), |ty| ctype(ty, exports))];
+        types.extend(c.args.iter().map(|ty| ctype(ty, exports)));
+        write!(
+            out,
+            "\n{} = ctypes.CFUNCTYPE({})\n",
+            c.name,
+            types.join(", ")
+        )
+        .unwrap();
+    }
+
+    out.push_str("\n\nclass AsyncPythonFfi:\n");
+    docstring(
+        &mut out,
+        "    ",
+        &["Spec for `cdll_with_spec` with all functions exported by the library".to_owned()],
+    );
+    for f in exports.enabled_functions() {
+        let ret = f.ret.as_ref().map(|ty| ctype(ty, exports));
+        signature(&mut out, f, |ty| ctype(ty, exports), ret, false);
+        if f.docs.is_empty() {
+            out.push_str("        ...\n\n");
+        } else {
+            docstring(&mut out, "        ", &f.docs);
+        }
+    }
+    out
+}
+
+/// Generate the stub for the module returned by `module`. The spec's
+/// functions are typed as they are called on the loaded library.
+pub fn stub(exports: &Exports) -> String {
+    let mut out = String::from(HEADER);
+    out.push_str("import ctypes\nimport enum\nfrom typing import Any\n\n");
+    for (name, _) in &exports.constants {
+        writeln!(out, "{}: int", name).unwrap();
+    }
+
+    for e in &exports.enums {
+        write!(out, "\nclass {}(enum.IntEnum):\n", e.name).unwrap();
+        for (name, _) in &e.variants {
+            writeln!(out, "    {}: int", constant_name(name)).unwrap();
+        }
+    }
+
+    for s in &exports.structs {
+        write!(out, "\nclass {}(ctypes.Structure):\n", s.name).unwrap();
+        for (name, ty) in &s.fields {
+            writeln!(out, "    {}: {}", name, pytype(ty, exports)).unwrap();
+        }
+        // The size is filled in by the constructor
+        let args: Vec<_> = s
+            .fields
+            .iter()
+            .skip(has_struct_size(s) as usize)
+            .map(|(name, ty)| format!("{}: {} = ...", name, pytype(ty, exports)))
+            .collect();
+        writeln!(
+            out,
+            "    def __init__(self, {}) -> None: ...",
+            args.join(", ")
+        )
+        .unwrap();
+    }
+
+    out.push('\n');
+    for c in &exports.callbacks {
+        writeln!(out, "{}: type[ctypes._CFuncPtr]", c.name).unwrap();
+    }
+
+    out.push_str("\nclass AsyncPythonFfi:\n");
+    for f in exports.enabled_functions() {
+        let ret = Some(
+            f.ret
+                .as_ref()
+                .map_or_else(|| "None".to_owned(), |ty| pytype(ty, exports)),
+        );
+        out.push_str("    @staticmethod\n");
+        signature(&mut out, f, |ty| pytype(ty, exports), ret, true);
+        out.push_str(" ...\n");
+    }
+    out
+}
+
+/// Return the structures ordered so that each comes after the structures its
+/// fields point to, since ctypes needs those to be defined first.
+fn structs_in_order(exports: &Exports) -> Vec<&Struct> {
+    let refers_to = |s: &Struct, other: &Struct| {
+        s.name != other.name
+            && s.fields.iter().any(|(_, ty)| {
+                let ty = match ty {
+                    Type::Ptr(ptr) => &*ptr.elem,
+                    ty => ty,
+                };
+                type_name(ty).as_deref() == Some(other.name.as_str())
+            })
+    };
+    let mut remaining: Vec<&Struct> = exports.structs.iter().collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while let Some(i) = remaining
+        .iter()
+        .position(|s| !remaining.iter().any(|other| refers_to(s, other)))
+    {
+        ordered.push(remaining.remove(i));
+    }
+    assert!(
+        remaining.is_empty(),
+        "structures refer to each other in a cycle"
+    );
+    ordered
+}
+
+/// Whether the structure starts with its own size, which is the case for all
+/// options structures.
+fn has_struct_size(s: &Struct) -> bool {
+    s.fields
+        .first()
+        .is_some_and(|(name, _)| name == "struct_size")
+}
+
+fn signature(
+    out: &mut String,
+    f: &Function,
+    map: impl Fn(&Type) -> String,
+    ret: Option<String>,
+    stub: bool,
+) {
+    let args: Vec<_> = f
+        .args
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, map(ty)))
+        .collect();
+    let ret = ret.map_or_else(String::new, |ret| format!(" -> {}", ret));
+    let line = format!("    def {}({}){}:", f.name, args.join(", "), ret);
+    if stub || line.len() <= 79 {
+        out.push_str(&line);
+    } else {
+        writeln!(out, "    def {}(", f.name).unwrap();
+        for arg in args {
+            writeln!(out, "        {},", arg).unwrap();
+        }
+        write!(out, "    ){}:", ret).unwrap();
+    }
+    if !stub {
+        out.push('\n');
+    }
+}
+
+fn docstring(out: &mut String, indent: &str, docs: &[String]) {
+    writeln!(out, "{}\"\"\"", indent).unwrap();
+    for line in docs {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            writeln!(out, "{}{}", indent, line.replace('\\', "\\\\")).unwrap();
+        }
+    }
+    writeln!(out, "{}\"\"\"\n", indent).unwrap();
+}
+
+/// Convert `InvalidArgument` into `INVALID_ARGUMENT`.
+fn constant_name(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
+
+fn primitive_ctype(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "c_char" => "ctypes.c_char",
+        "c_int" => "ctypes.c_int",
+        "c_uint" => "ctypes.c_uint",
+        "c_long" => "ctypes.c_long",
+        "c_ulong" => "ctypes.c_ulong",
+        "bool" => "ctypes.c_bool",
+        "i8" => "ctypes.c_int8",
+        "u8" => "ctypes.c_uint8",
+        "i16" => "ctypes.c_int16",
+        "u16" => "ctypes.c_uint16",
+        "i32" => "ctypes.c_int32",
+        "u32" => "ctypes.c_uint32",
+        "i64" => "ctypes.c_int64",
+        "u64" => "ctypes.c_uint64",
+        "isize" => "ctypes.c_ssize_t",
+        "usize" => "ctypes.c_size_t",
+        "f32" => "ctypes.c_float",
+        "f64" => "ctypes.c_double",
+        _ => return None,
+    })
+}
+
+/// Map a Rust type to its ctypes equivalent. Pointers to types that aren't
+/// exported are opaque handles.
+fn ctype(ty: &Type, exports: &Exports) -> String {
+    match ty {
+        Type::Ptr(ptr) => {
+            let name = type_name(&ptr.elem).unwrap_or_default();
+            if name == "c_char" {
+                "ctypes.c_char_p".to_owned()
+            } else if let Some(prim) = primitive_ctype(&name) {
+                format!("ctypes.POINTER({})", prim)
+            } else if exports.structs.iter().any(|s| s.name == name) {
+                format!("ctypes.POINTER({})", name)
+            } else if exports.enums.iter().any(|e| e.name == name) {
+                "ctypes.POINTER(ctypes.c_int)".to_owned()
+            } else {
+                "ctypes.c_void_p".to_owned()
+            }
+        }
+        _ => {
+            let name = type_name(ty).unwrap_or_else(|| panic!("unsupported type in C ABI"));
+            if let Some(prim) = primitive_ctype(&name) {
+                prim.to_owned()
+            } else if exports.enums.iter().any(|e| e.name == name) {
+                "ctypes.c_int".to_owned()
+            } else if exports.callbacks.iter().any(|c| c.name == name)
+                || exports.structs.iter().any(|s| s.name == name)
+            {
+                name
+            } else {
+                panic!("unsupported type in C ABI: {}", name)
+            }
+        }
+    }
+}
+
+/// Map a Rust type to the Python type that ctypes converts it to and from.
+fn pytype(ty: &Type, exports: &Exports) -> String {
+    match ty {
+        Type::Ptr(ptr) if type_name(&ptr.elem).as_deref() == Some("c_char") => {
+            "bytes | None".to_owned()
+        }
+        Type::Ptr(_) => "Any".to_owned(),
+        _ => {
+            let name = type_name(ty).unwrap_or_default();
+            match name.as_str() {
+                "bool" => "bool".to_owned(),
+                "f32" | "f64" => "float".to_owned(),
+                "c_char" => "bytes".to_owned(),
+                _ if primitive_ctype(&name).is_some() => "int".to_owned(),
+                _ if exports.enums.iter().any(|e| e.name == name) => "int".to_owned(),
+                _ => name,
+            }
+        }
+    }
+}
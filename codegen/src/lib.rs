@@ -0,0 +1,157 @@
+//! Description of the functions that `#[async_ffi]` exports. It's shared by
+//! the macro, which generates them, and the build script, which generates the
+//! bindings and the manifest for them without expanding the macro, so the two
+//! can't disagree on their names, signatures or documentation.
+
+use syn::{parse_quote, FnArg, GenericArgument, Pat, PathArguments, ReturnType, Signature, Type};
+
+/// A function exported by `#[async_ffi]`.
+pub struct Export {
+    /// What the function does, like `start` or `free`. The function's name is
+    /// the prefix followed by an underscore and this.
+    pub suffix: &'static str,
+    pub name: String,
+    pub args: Vec<(String, Type)>,
+    pub ret: Type,
+    pub docs: Vec<String>,
+    /// Whether the caller must uphold the safety requirements in the docs.
+    pub is_unsafe: bool,
+}
+
+/// Return the functions exported for the function `ident` by
+/// `#[async_ffi(name = prefix)]`. `args` are the arguments of the function,
+/// which are passed to `_start` after the context, and `value` is the `T` of
+/// its `Result<T, Error>`, or `None` if it's `()`.
+///
+/// The types are fully qualified paths within the `async-python-ffi` crate.
+pub fn async_ffi_exports(
+    ident: &str,
+    prefix: &str,
+    args: Vec<(String, Type)>,
+    value: Option<&Type>,
+) -> Vec<Export> {
+    let c_int: Type = parse_quote!(::std::os::raw::c_int);
+    let op_const: Type = parse_quote!(*const crate::op::RustOp);
+    let op_mut: Type = parse_quote!(*mut crate::op::RustOp);
+    let op_arg = |ty: &Type| vec![("op".to_owned(), ty.clone())];
+    let lines =
+        |text: &[&str]| -> Vec<String> { text.iter().map(|&line| line.to_owned()).collect() };
+
+    let mut start_args = vec![("ctx".to_owned(), parse_quote!(*const crate::ctx::RustCtx))];
+    start_args.extend(args);
+    let start_docs = vec![
+        format!(
+            "Start `{}` with the deadline and scope of `ctx` and return a",
+            ident
+        ),
+        "handle to it, or null if the library panicked. The handle must be".to_owned(),
+        format!("freed using `{}_free`.", prefix),
+    ];
+    let poll_docs = lines(&["Return the status of the operation, or `-1` if it's still running."]);
+    let cancel_docs = lines(&[
+        "Request cancellation of the operation. Returns `0` on success and",
+        "`InvalidHandle` if the handle has been freed.",
+    ]);
+    let mut result_args = op_arg(&op_const);
+    let result_docs = match value {
+        Some(value) => {
+            result_args.push(("out".to_owned(), parse_quote!(*mut #value)));
+            lines(&[
+                "Write the operation's value to `out` if it completed",
+                "successfully and return its status, or `-1` if it's still",
+                "running.",
+                "",
+                "# Safety",
+                "",
+                "`out` must be null or valid for writes.",
+            ])
+        }
+        None => poll_docs.clone(),
+    };
+    let free_docs = lines(&[
+        "Release the handle, cancelling the operation if it's still running.",
+        "Returns `0` on success and `InvalidHandle` if the handle has already",
+        "been freed.",
+    ]);
+
+    let exports = [
+        ("start", start_args, op_mut.clone(), start_docs, false),
+        ("poll", op_arg(&op_const), c_int.clone(), poll_docs, false),
+        (
+            "cancel",
+            op_arg(&op_const),
+            c_int.clone(),
+            cancel_docs,
+            false,
+        ),
+        (
+            "result",
+            result_args,
+            c_int.clone(),
+            result_docs,
+            value.is_some(),
+        ),
+        ("free", op_arg(&op_mut), c_int, free_docs, false),
+    ];
+    exports
+        .into_iter()
+        .map(|(suffix, args, ret, docs, is_unsafe)| Export {
+            suffix,
+            name: format!("{}_{}", prefix, suffix),
+            args,
+            ret,
+            docs,
+            is_unsafe,
+        })
+        .collect()
+}
+
+/// Return the names and types of the arguments of a function, which must be
+/// plain identifiers.
+pub fn fn_args(sig: &Signature) -> syn::Result<Vec<(String, Type)>> {
+    sig.inputs
+        .iter()
+        .map(|arg| match arg {
+            FnArg::Typed(arg) => match &*arg.pat {
+                Pat::Ident(pat) => Ok((pat.ident.to_string(), (*arg.ty).clone())),
+                pat => Err(syn::Error::new_spanned(
+                    pat,
+                    "arguments of exported functions must be plain identifiers",
+                )),
+            },
+            FnArg::Receiver(arg) => Err(syn::Error::new_spanned(
+                arg,
+                "exported functions can't take self",
+            )),
+        })
+        .collect()
+}
+
+/// Extract `T` from the return type `Result<T, Error>` of an `#[async_ffi]`
+/// function, or `None` if it's `()`.
+pub fn result_value(output: &ReturnType) -> syn::Result<Option<&Type>> {
+    let error =
+        || syn::Error::new_spanned(output, "async_ffi functions must return Result<T, Error>");
+    let ty = match output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => return Err(error()),
+    };
+    let segment = match &**ty {
+        Type::Path(path) => path.path.segments.last().ok_or_else(error)?,
+        _ => return Err(error()),
+    };
+    if segment.ident != "Result" {
+        return Err(error());
+    }
+    let value = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first() {
+            Some(GenericArgument::Type(ty)) => ty,
+            _ => return Err(error()),
+        },
+        _ => return Err(error()),
+    };
+    match value {
+        Type::Tuple(tuple) if tuple.elems.is_empty() => Ok(None),
+        _ => Ok(Some(value)),
+    }
+}
@@ -1,21 +1,151 @@
 import asyncio
 import ctypes
+import time
 from pathlib import Path
-from ffilib import cdll_with_spec, DeferredCaller
-
-
-class AsyncPythonFfi:
-    def rust_sleep(delay_ms: ctypes.c_int) -> ctypes.c_int:
-        ...
+from async_python_ffi import (
+    ABI_VERSION,
+    AsyncPythonFfi,
+    ErrorCode,
+    RustCompletion,
+    RustCtxConfig,
+    RustFfiConfig,
+    RustFfiReport,
+    RustMissedTick,
+    RustPoolConfig,
+)
+from ffilib import (
+    cdll_with_spec,
+    completion_future,
+    CompletionQueue,
+    DeferredCaller,
+    fd_readable,
+)
 
 
+# The library's manifest is checked against the spec, so loading a library
+# that doesn't match the bindings fails instead of crashing later on
 LIB = cdll_with_spec(
     Path(__file__).parent / "libasync_python_ffi.so",
     AsyncPythonFfi,
+    manifest="rust_ffi_manifest",
+    abi_version=ABI_VERSION,
 )
 deferr = DeferredCaller(LIB)
 
 
+class RustPanicError(RuntimeError):
+    """
+    Raised when the library panicked while running an operation
+    """
+
+
+# Exceptions to raise for the library's error codes
+ERRORS = {
+    ErrorCode.INVALID_ARGUMENT: ValueError,
+    ErrorCode.CANCELLED: asyncio.CancelledError,
+    ErrorCode.TIMEOUT: TimeoutError,
+    ErrorCode.PANIC: RustPanicError,
+    ErrorCode.OS: OSError,
+    ErrorCode.INVALID_STATE: RuntimeError,
+    ErrorCode.ABI_MISMATCH: ImportError,
+    ErrorCode.INTERRUPTED: InterruptedError,
+    ErrorCode.INVALID_HANDLE: ValueError,
+}
+
+
+def raise_for_status(status):
+    """
+    Raise an exception for the given status if it's not 0. The message is the
+    last error reported on the calling thread, so this must be called directly
+    after the call that returned the status.
+    """
+    if status == 0:
+        return
+    buf = ctypes.create_string_buffer(1024)
+    LIB.rust_last_error_message(buf, len(buf))
+    message = buf.value.decode(errors="replace")
+    raise ERRORS.get(status, RuntimeError)(message)
+
+
+# Use a pool of four worker threads for operations started by the library
+POOL_CONFIG = RustPoolConfig(4, b"example-worker", 0)
+raise_for_status(LIB.rust_ffi_init(ctypes.byref(RustFfiConfig(ctypes.pointer(POOL_CONFIG)))))
+
+
+async def rust_sleep(delay_ms, scope=None, ctx=None):
+    """
+    Cancellable version of rust_sleep that doesn't need a DeferredCaller. The
+    library calls us back from its own thread once the sleep is done and if
+    the awaiting task is cancelled the Rust operation is cancelled too. The
+    sleep is also cancelled along with the given cancellation scope, and fails
+    with TimeoutError if it's still running at the deadline of the context.
+    """
+    fut, callback = completion_future()
+    op = LIB.rust_sleep_callback(ctx, delay_ms, callback, None)
+    if scope is not None:
+        LIB.rust_scope_add(scope, op)
+
+    # Freeing the handle cancels the sleep if it's still running. The callback
+    # runs on another thread, so the error is fetched by polling the operation
+    try:
+        await fut
+        raise_for_status(LIB.rust_op_poll(op))
+    finally:
+        LIB.rust_op_free(op)
+
+
+async def rust_sleep_cq(cq, delay_ms):
+    """
+    Same as rust_sleep, but waits for the completion through the library's
+    completion queue instead of a callback
+    """
+    op = LIB.rust_sleep_submit(None, delay_ms)
+    try:
+        await cq.wait(LIB.rust_op_id(op))
+        raise_for_status(LIB.rust_op_poll(op))
+    finally:
+        LIB.rust_op_free(op)
+
+
+async def rust_sleep_fd(delay_ms):
+    """
+    Same as rust_sleep, but waits on a timerfd so neither the library nor
+    Python needs a thread for it. Cancelling the awaiting task closes the fd,
+    which is all there is to cancelling the sleep.
+    """
+    fd = LIB.rust_sleep_fd(delay_ms)
+    if fd < 0:
+        raise_for_status(LIB.rust_last_error_code())
+    try:
+        await fd_readable(fd)
+    finally:
+        raise_for_status(LIB.rust_sleep_fd_close(fd))
+
+
+async def rust_interval(period_ms, count):
+    """
+    Yield the times of the next count ticks of an interval timer, in
+    nanoseconds on the monotonic clock. Unlike looping over rust_sleep the
+    ticks follow a fixed schedule, so they don't drift.
+    """
+    interval = LIB.rust_interval_new(period_ms * 1_000_000, 0, RustMissedTick.SKIP)
+    if not interval:
+        raise_for_status(LIB.rust_last_error_code())
+    try:
+        for _ in range(count):
+            fut, callback = completion_future()
+            op = LIB.rust_interval_next_callback(None, interval, callback, None)
+            try:
+                await fut
+                tick = ctypes.c_int64()
+                raise_for_status(LIB.rust_interval_next_result(op, ctypes.byref(tick)))
+            finally:
+                LIB.rust_op_free(op)
+            yield tick.value
+    finally:
+        LIB.rust_interval_free(interval)
+
+
 async def count_sheep():
     for i in range(1, 6):
         await asyncio.sleep(1)
@@ -28,8 +158,101 @@ async def ffi_sleep():
     print("Post-sleep")
 
 
+async def ffi_sleep_cancelled():
+    try:
+        await asyncio.wait_for(rust_sleep(60_000), timeout=2)
+    except asyncio.TimeoutError:
+        print("Cancelled a 60 second sleep after 2 seconds")
+
+
+async def ffi_sleep_many():
+    cq = CompletionQueue(
+        LIB.rust_cq_fd(),
+        LIB.rust_cq_drain,
+        record_type=RustCompletion,
+    )
+    await asyncio.gather(*(rust_sleep_cq(cq, 1000) for _ in range(3)))
+    cq.close()
+    print("Slept three times using the completion queue")
+
+
+async def ffi_sleep_fd():
+    await asyncio.gather(*(rust_sleep_fd(1000) for _ in range(3)))
+    print("Slept three times using timerfds")
+
+
+async def ffi_interval():
+    first = None
+    async for tick in rust_interval(200, 5):
+        first = first or tick
+        print(f"Tick at {(tick - first) / 1e6:.0f} ms")
+
+
+async def ffi_sleep_scope():
+    scope = LIB.rust_scope_new(None)
+    try:
+        sleeps = asyncio.gather(
+            *(rust_sleep(60_000, scope) for _ in range(3)),
+            return_exceptions=True,
+        )
+        await asyncio.sleep(1)
+        LIB.rust_scope_cancel(scope)
+        results = await sleeps
+    finally:
+        LIB.rust_scope_free(scope)
+    cancelled = sum(isinstance(r, asyncio.CancelledError) for r in results)
+    print(f"Cancelled {cancelled} sleeps at once using a scope")
+
+
+async def ffi_sleep_deadline():
+    # Like a request handler with a two second budget, which the library
+    # enforces for every operation started with the context
+    deadline = time.monotonic_ns() + 2_000_000_000
+    ctx = LIB.rust_ctx_new(ctypes.byref(RustCtxConfig(deadline, None, 42)))
+    try:
+        await rust_sleep(60_000, ctx=ctx)
+    except TimeoutError as e:
+        print(f"Timed out a 60 second sleep: {e}")
+    finally:
+        LIB.rust_ctx_free(ctx)
+
+
+async def ffi_sleep_runtime():
+    # A component that owns its workers, so freeing its runtime cancels its
+    # sleeps without touching anyone else's
+    config = RustPoolConfig(2, b"component-worker", 0)
+    runtime = LIB.rust_runtime_new(ctypes.byref(config))
+    ctx = LIB.rust_ctx_new(ctypes.byref(RustCtxConfig(0, None, 0, runtime)))
+    try:
+        sleeps = asyncio.gather(
+            *(rust_sleep(60_000, ctx=ctx) for _ in range(10)),
+            return_exceptions=True,
+        )
+        await asyncio.sleep(0.5)
+        await deferr.rust_runtime_free(runtime)
+        results = await sleeps
+    finally:
+        LIB.rust_ctx_free(ctx)
+    cancelled = sum(isinstance(r, asyncio.CancelledError) for r in results)
+    print(f"Cancelled {cancelled} sleeps by freeing their runtime")
+
+
 async def main():
     await asyncio.gather(count_sheep(), ffi_sleep())
+    await ffi_sleep_cancelled()
+    await ffi_sleep_many()
+    await ffi_sleep_fd()
+    await ffi_interval()
+    await ffi_sleep_scope()
+    await ffi_sleep_deadline()
+    await ffi_sleep_runtime()
+
+    # Give anything still running a second to finish before stopping the
+    # library's threads, and make sure no handles were leaked
+    report = RustFfiReport()
+    raise_for_status(LIB.rust_ffi_shutdown(1000, ctypes.byref(report)))
+    leaked = report.ops + report.scopes + report.intervals + report.contexts + report.runtimes
+    print(f"Shut down with {report.cancelled} operations cancelled and {leaked} handles leaked")
 
 
 asyncio.run(main())
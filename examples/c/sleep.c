@@ -0,0 +1,138 @@
+/*
+ * Example of using the library's async API from C. Sleeps are started in
+ * three different ways: waiting on the handle, being called back from a
+ * worker thread and waiting for records on the completion queue.
+ */
+#include <poll.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include <async_python_ffi.h>
+
+static void fail(const char *what) {
+    char message[256];
+    rust_last_error_message(message, sizeof(message));
+    fprintf(stderr, "%s failed: %s\n", what, message);
+    exit(1);
+}
+
+/* Block until the sleep has finished */
+static void sleep_wait(void) {
+    RustOp *op = rust_sleep_start(NULL, 500);
+    if (op == NULL) {
+        fail("rust_sleep_start");
+    }
+    if (rust_op_wait(op) != RUST_STATUS_OK) {
+        fail("rust_op_wait");
+    }
+    rust_op_free(op);
+    printf("Slept while waiting on the handle\n");
+}
+
+/* Cancel a sleep that would otherwise run for a minute */
+static void sleep_cancel(void) {
+    RustOp *op = rust_sleep_start(NULL, 60000);
+    if (op == NULL) {
+        fail("rust_sleep_start");
+    }
+    rust_op_cancel(op);
+    if (rust_op_wait(op) != RUST_ERROR_CODE_CANCELLED) {
+        fail("cancelling the sleep");
+    }
+    rust_op_free(op);
+    printf("Cancelled a 60 second sleep\n");
+}
+
+struct completion {
+    pthread_mutex_t mutex;
+    pthread_cond_t done;
+    int status;
+};
+
+/* Called from a worker thread once the sleep has finished */
+static void on_complete(void *user_data, int status) {
+    struct completion *c = user_data;
+    pthread_mutex_lock(&c->mutex);
+    c->status = status;
+    pthread_cond_signal(&c->done);
+    pthread_mutex_unlock(&c->mutex);
+}
+
+static void sleep_callback(void) {
+    struct completion c = {
+        PTHREAD_MUTEX_INITIALIZER,
+        PTHREAD_COND_INITIALIZER,
+        RUST_STATUS_PENDING,
+    };
+    RustOp *op = rust_sleep_callback(NULL, 500, on_complete, &c);
+    if (op == NULL) {
+        fail("rust_sleep_callback");
+    }
+
+    pthread_mutex_lock(&c.mutex);
+    while (c.status == RUST_STATUS_PENDING) {
+        pthread_cond_wait(&c.done, &c.mutex);
+    }
+    pthread_mutex_unlock(&c.mutex);
+
+    if (c.status != RUST_STATUS_OK) {
+        fprintf(stderr, "sleep failed with status %d\n", c.status);
+        exit(1);
+    }
+    rust_op_free(op);
+    printf("Slept until called back\n");
+}
+
+/* Start several sleeps and wait for all of them using the completion queue */
+static void sleep_many(void) {
+    enum { COUNT = 3 };
+    RustOp *ops[COUNT];
+    for (int i = 0; i < COUNT; i++) {
+        ops[i] = rust_sleep_submit(NULL, 500);
+        if (ops[i] == NULL) {
+            fail("rust_sleep_submit");
+        }
+    }
+
+    struct pollfd pfd = { .fd = rust_cq_fd(), .events = POLLIN };
+    if (pfd.fd < 0) {
+        fail("rust_cq_fd");
+    }
+    int remaining = COUNT;
+    while (remaining > 0) {
+        if (poll(&pfd, 1, -1) < 0) {
+            perror("poll");
+            exit(1);
+        }
+
+        RustCompletion records[COUNT];
+        int n = rust_cq_drain(records, COUNT);
+        if (n < 0) {
+            fail("rust_cq_drain");
+        }
+        for (int i = 0; i < n; i++) {
+            if (records[i].status != RUST_STATUS_OK) {
+                fprintf(stderr, "sleep failed with status %d\n", records[i].status);
+                exit(1);
+            }
+        }
+        remaining -= n;
+    }
+
+    for (int i = 0; i < COUNT; i++) {
+        rust_op_free(ops[i]);
+    }
+    printf("Slept %d times using the completion queue\n", COUNT);
+}
+
+int main(void) {
+    sleep_wait();
+    sleep_cancel();
+    sleep_callback();
+    sleep_many();
+    if (rust_ffi_shutdown(1000, NULL) != RUST_STATUS_OK) {
+        fail("rust_ffi_shutdown");
+    }
+    return 0;
+}
@@ -136,7 +136,216 @@ class DeferredCaller:
         return wrapper
 
 
-def cdll_with_spec(lib_path, spec):
+COMPLETION_CALLBACK = ctypes.CFUNCTYPE(None, ctypes.c_void_p, ctypes.c_int)
+
+# Callbacks handed to C must be kept alive until they have been called
+_pending_callbacks = {}
+
+
+def completion_future():
+    """
+    Create a future and a C callback that resolves it with the status it's
+    called with. The callback can safely be called from any thread, but it
+    must be called exactly once.
+
+    ```
+    fut, callback = completion_future()
+    LIB.start_some_operation(callback, None)
+    status = await fut
+    ```
+    """
+
+    loop = asyncio.get_running_loop()
+    fut = loop.create_future()
+
+    def set_status(status):
+        if not fut.done():
+            fut.set_result(status)
+
+    @COMPLETION_CALLBACK
+    def callback(user_data, status):
+        _pending_callbacks.pop(id(callback), None)
+        loop.call_soon_threadsafe(set_status, status)
+
+    _pending_callbacks[id(callback)] = callback
+    return fut, callback
+
+
+class Completion(ctypes.Structure):
+    """
+    Completion record as returned by a completion queue's drain function
+    """
+
+    _fields_ = [
+        ("op_id", ctypes.c_uint64),
+        ("status", ctypes.c_int),
+        ("result", ctypes.c_int64),
+    ]
+
+
+class CompletionQueue:
+    """
+    Wait for operations to complete using a completion queue exposed by a C
+    library. The queue consists of a file descriptor that becomes readable when
+    there are completion records available, and a drain function with the
+    signature `int drain(Completion *buf, size_t n)` that pops up to n records
+    and returns how many it wrote. No threads are involved on the Python side
+    since the fd is watched by the event loop.
+
+    ```
+    cq = CompletionQueue(LIB.rust_cq_fd(), LIB.rust_cq_drain)
+    op = LIB.start_some_operation()
+    status = await cq.wait(LIB.op_id(op))
+    ```
+
+    The records are read as Completion structures unless another structure
+    with the same fields is given as record_type.
+
+    Note that a CompletionQueue is bound to the running event loop when it's
+    created.
+    """
+
+    def __init__(self, fd, drain, batch_size=64, record_type=Completion):
+        if fd < 0:
+            raise OSError("Completion queue is not available")
+        self.fd = fd
+        self.drain = drain
+        self.buf = (record_type * batch_size)()
+        self.waiters = {}
+        self.loop = asyncio.get_running_loop()
+        self.loop.add_reader(fd, self._on_readable)
+
+    def close(self):
+        self.loop.remove_reader(self.fd)
+
+    def _on_readable(self):
+        n = self.drain(self.buf, len(self.buf))
+        for record in self.buf[:max(n, 0)]:
+            fut = self.waiters.pop(record.op_id, None)
+            if fut is not None and not fut.done():
+                fut.set_result(record.status)
+
+    def wait(self, op_id):
+        """
+        Return a future that resolves to the status of the given operation.
+        This must be called before control is given back to the event loop
+        after starting the operation or its completion may be missed.
+        """
+        fut = self.loop.create_future()
+        self.waiters[op_id] = fut
+        fut.add_done_callback(lambda _: self.waiters.pop(op_id, None))
+        return fut
+
+
+async def fd_readable(fd):
+    """
+    Wait until the given file descriptor becomes readable. The fd is watched by
+    the running event loop, so no threads are involved.
+
+    ```
+    fd = LIB.start_some_timer()
+    await fd_readable(fd)
+    ```
+    """
+    loop = asyncio.get_running_loop()
+    fut = loop.create_future()
+
+    def on_readable():
+        if not fut.done():
+            fut.set_result(None)
+
+    loop.add_reader(fd, on_readable)
+    try:
+        await fut
+    finally:
+        loop.remove_reader(fd)
+
+
+class FfiFunction(ctypes.Structure):
+    """
+    Description of an exported function in a library's manifest
+    """
+
+    _fields_ = [
+        ("name", ctypes.c_char_p),
+        ("arg_types", ctypes.POINTER(ctypes.c_int)),
+        ("arg_count", ctypes.c_size_t),
+        ("return_type", ctypes.c_int),
+        ("kind", ctypes.c_int),
+    ]
+
+
+class FfiManifest(ctypes.Structure):
+    """
+    Manifest of a library's exported functions as returned by its manifest
+    function
+    """
+
+    _fields_ = [
+        ("abi_version", ctypes.c_uint32),
+        ("functions", ctypes.POINTER(FfiFunction)),
+        ("function_count", ctypes.c_size_t),
+    ]
+
+
+# ctypes types for the type codes used in manifests. Callbacks are passed as
+# plain pointers since the manifest doesn't describe their signature
+MANIFEST_TYPES = {
+    0: None,
+    1: ctypes.c_int,
+    2: ctypes.c_int64,
+    3: ctypes.c_uint64,
+    4: ctypes.c_size_t,
+    5: ctypes.c_bool,
+    6: ctypes.c_char_p,
+    7: ctypes.c_void_p,
+    8: ctypes.c_void_p,
+    9: ctypes.c_uint32,
+}
+
+
+class ManifestError(ImportError):
+    """
+    Raised when a library doesn't match the bindings that loaded it
+    """
+
+
+def read_manifest(lib, manifest):
+    """
+    Call the given manifest function of the library and return its ABI version
+    and a dict mapping the names of its exported functions to their argument
+    types, return type and kind. The kind tells whether the function returns
+    immediately (0), starts an operation (1) or blocks (2).
+    """
+
+    manifest_func = getattr(lib, manifest)
+    manifest_func.argtypes = []
+    manifest_func.restype = ctypes.POINTER(FfiManifest)
+    table = manifest_func()
+    if not table:
+        raise ManifestError(f"{manifest}() returned null")
+    table = table.contents
+
+    functions = {}
+    for func in table.functions[:table.function_count]:
+        argtypes = [MANIFEST_TYPES[t] for t in func.arg_types[:func.arg_count]]
+        restype = MANIFEST_TYPES[func.return_type]
+        functions[func.name.decode()] = (argtypes, restype, func.kind)
+    return table.abi_version, functions
+
+
+def _is_compatible(expected, actual):
+    # Pointers are only described as pointers by the manifest, so any kind of
+    # pointer in the spec is accepted
+    if expected is ctypes.c_void_p:
+        return actual is ctypes.c_void_p or issubclass(
+            actual,
+            (ctypes._Pointer, ctypes._CFuncPtr),
+        )
+    return expected is actual
+
+
+def cdll_with_spec(lib_path, spec=None, manifest=None, abi_version=None):
     """
     Open a shared library using ctypes.CDLL and apply the given spec class'
     type annotations to the argtypes and restype attributes of the shared
@@ -157,31 +366,78 @@ def cdll_with_spec(lib_path, spec):
 
     LIB = cdll_with_spec("path/to/lib.so", LibrarySpec)
     ```
+
+    If the library describes itself through a manifest function, its name can
+    be given as manifest. The spec is then verified against the manifest, and
+    ManifestError is raised if the library doesn't export a function in the
+    spec, if the types differ or if its ABI version is not abi_version. Without
+    a spec all functions in the manifest are configured using its types.
+
+    ```
+    LIB = cdll_with_spec("path/to/lib.so", manifest="rust_ffi_manifest")
+    ```
     """
 
+    lib = ctypes.CDLL(lib_path)
+    if manifest is not None:
+        lib_abi_version, functions = read_manifest(lib, manifest)
+        if abi_version is not None and lib_abi_version != abi_version:
+            raise ManifestError(
+                f"{lib_path} has ABI version {lib_abi_version}, expected "
+                f"{abi_version}"
+            )
+    elif spec is None:
+        raise TypeError("Either a spec or a manifest must be given")
+
+    if spec is None:
+        for name, (argtypes, restype, _) in functions.items():
+            lib_func = getattr(lib, name)
+            lib_func.argtypes = argtypes
+            lib_func.restype = restype
+        return lib
+
     # The given spec must be a class
     assert inspect.isclass(spec)
 
     # Find all methods that non-internal methods
-    spec_funcs = (
+    spec_funcs = [
         type_
         for name, type_ in inspect.getmembers(spec)
         if not name.startswith("__") and inspect.isfunction(type_)
-    )
+    ]
 
     # There has to be at least one function in the spec, or it's likely an error
     assert spec_funcs
 
-    lib = ctypes.CDLL(lib_path)
     for spec_func in spec_funcs:
         # Inspect the signature of the spec function
         sig = inspect.signature(spec_func)
+        argtypes = [p.annotation for p in sig.parameters.values()]
+        if sig.return_annotation is not sig.empty:
+            restype = sig.return_annotation
+        else:
+            restype = None
+
+        if manifest is not None:
+            name = spec_func.__name__
+            if name not in functions:
+                raise ManifestError(f"{lib_path} doesn't export {name}")
+            expected_argtypes, expected_restype, _ = functions[name]
+            if (
+                len(argtypes) != len(expected_argtypes)
+                or not all(map(_is_compatible, expected_argtypes, argtypes))
+                or (restype is None) != (expected_restype is None)
+                or (
+                    restype is not None
+                    and not _is_compatible(expected_restype, restype)
+                )
+            ):
+                raise ManifestError(
+                    f"{name} in {lib_path} doesn't match the spec"
+                )
 
         # Apply the types to the lib's function
         lib_func = getattr(lib, spec_func.__name__)
-        lib_func.argtypes = [p.annotation for p in sig.parameters.values()]
-        if sig.return_annotation is not sig.empty:
-            lib_func.restype = sig.return_annotation
-        else:
-            lib_func.restype = None
+        lib_func.argtypes = argtypes
+        lib_func.restype = restype
     return lib
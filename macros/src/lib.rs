@@ -0,0 +1,160 @@
+//! Procedural macros for `async-python-ffi`. These expand to paths within the
+//! `async-python-ffi` crate, so they can only be used from within it.
+
+use async_python_ffi_codegen::{async_ffi_exports, fn_args, result_value};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Ident, ItemFn, LitStr};
+
+/// Export an `async fn` over the C ABI as a set of functions operating on an
+/// operation handle. The function must return `Result<T, Error>` where `T`
+/// implements `OpValue`, and its arguments must be `Send + 'static` since
+/// they are moved into the operation's task.
+///
+/// ```ignore
+/// #[async_ffi(name = "rust_sleep")]
+/// async fn sleep(delay_ms: c_int) -> Result<(), Error> {
+///     ...
+/// }
+/// ```
+///
+/// This exports the following functions, where the prefix defaults to the name
+/// of the function:
+///
+/// - `rust_sleep_start(ctx, delay_ms)` starts the operation with the deadline
+///   and scope of the given context, which may be null, and returns its
+///   handle.
+/// - `rust_sleep_poll(op)` returns the status, or `-1` if it's still running.
+/// - `rust_sleep_cancel(op)` requests cancellation of the operation.
+/// - `rust_sleep_result(op, out)` writes the operation's value to `out` and
+///   returns the same status as `_poll`. The `out` argument is omitted when
+///   `T` is `()`.
+/// - `rust_sleep_free(op)` releases the handle, cancelling the operation if
+///   it's still running.
+///
+/// Panics while running the operation fail it with the `Panic` error instead
+/// of unwinding into the executor.
+#[proc_macro_attribute]
+pub fn async_ffi(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?.value());
+            Ok(())
+        } else {
+            Err(meta.error("unsupported async_ffi property"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+
+    let func = parse_macro_input!(item as ItemFn);
+    match expand(name, func) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Mark an exported function as blocking the calling thread until some work
+/// has finished. The function is left unchanged, the marker is only read by the
+/// build script when it generates the manifest of exported functions.
+#[proc_macro_attribute]
+pub fn blocking(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(Span::call_site(), "blocking takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    item
+}
+
+fn expand(name: Option<String>, func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &func.sig;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            "async_ffi can only be used on async functions",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "async_ffi functions can't be generic",
+        ));
+    }
+
+    let args = fn_args(sig)?;
+    let arg_names: Vec<_> = args.iter().map(|(name, _)| ident(name)).collect();
+    let value = result_value(&sig.output)?;
+
+    let func_ident = &sig.ident;
+    let prefix = name.unwrap_or_else(|| func_ident.to_string());
+    let helper = format_ident!("__async_ffi_{}", prefix);
+    let exports = async_ffi_exports(&func_ident.to_string(), &prefix, args, value);
+
+    let functions = exports.iter().map(|export| {
+        let body = match export.suffix {
+            "start" => quote! {
+                crate::panic::catch_panic(::std::ptr::null_mut(), || {
+                    crate::op::RustOp::into_raw(#helper(ctx, crate::op::Op::new(), #(#arg_names),*))
+                })
+            },
+            "poll" => quote!(crate::op::rust_op_poll(op)),
+            "cancel" => quote!(crate::op::rust_op_cancel(op)),
+            "result" => match value {
+                Some(value) => quote! {
+                    crate::panic::catch_panic_status(|| crate::op::result::<#value>(op, out))
+                },
+                None => quote! {
+                    crate::panic::catch_panic_status(|| unsafe {
+                        crate::op::result::<()>(op, ::std::ptr::null_mut())
+                    })
+                },
+            },
+            "free" => quote!(crate::op::rust_op_free(op)),
+            suffix => unreachable!("unknown async_ffi export {}", suffix),
+        };
+        // Doc comments are written with a space after the slashes
+        let docs = export.docs.iter().map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!(" {}", line)
+            }
+        });
+        let unsafety = export.is_unsafe.then(|| quote!(unsafe));
+        let name = ident(&export.name);
+        let arg_names = export.args.iter().map(|(name, _)| ident(name));
+        let arg_types = export.args.iter().map(|(_, ty)| ty);
+        let ret = &export.ret;
+        quote! {
+            #(#[doc = #docs])*
+            #[no_mangle]
+            pub #unsafety extern "C" fn #name(#(#arg_names: #arg_types),*) -> #ret {
+                #body
+            }
+        }
+    });
+
+    let arg_types = exports[0].args[1..].iter().map(|(_, ty)| ty);
+    Ok(quote! {
+        #func
+
+        #(#functions)*
+
+        /// Start the operation for the given operation state. This is used by
+        /// variants that report their completion in other ways.
+        #[allow(dead_code)]
+        fn #helper(
+            ctx: *const crate::ctx::RustCtx,
+            op: ::std::sync::Arc<crate::op::Op>,
+            #(#arg_names: #arg_types),*
+        ) -> ::std::sync::Arc<crate::op::Op> {
+            crate::ctx::spawn(ctx, op, #func_ident(#(#arg_names),*))
+        }
+    })
+}
+
+fn ident(name: &str) -> Ident {
+    Ident::new(name, Span::call_site())
+}
@@ -0,0 +1,34 @@
+use std::mem;
+
+use crate::error::Error;
+use crate::panic::catch_panic;
+
+/// Version of the C ABI. It's increased whenever an exported function or
+/// structure changes in a way that is incompatible with existing bindings.
+pub const ABI_VERSION: u32 = 5;
+
+/// Check the `struct_size` field of an options structure given by the caller.
+/// Options structures start with their size as seen by the caller, so a
+/// binding built for another layout is rejected instead of having the library
+/// read past the end of the structure.
+pub fn check_struct_size<T>(struct_size: usize, name: &str) -> Result<(), Error> {
+    let expected = mem::size_of::<T>();
+    if struct_size == expected {
+        Ok(())
+    } else {
+        Err(Error::abi_mismatch(format!(
+            "{} is {} bytes but the library expects {}, the bindings were built \
+             for another version of the library",
+            name, struct_size, expected,
+        )))
+    }
+}
+
+/// Return the version of the library's C ABI. Bindings should check that it's
+/// the version they were built for before calling anything else, since the
+/// layouts of structures and the signatures of functions may differ between
+/// versions.
+#[no_mangle]
+pub extern "C" fn rust_ffi_abi_version() -> u32 {
+    catch_panic(0, || ABI_VERSION)
+}
@@ -0,0 +1,49 @@
+//! Executors that run operations in the background. Exactly one is compiled in
+//! depending on whether the `tokio` feature is enabled, and they all expose
+//! the same interface so the C ABI doesn't depend on the backend.
+
+#[cfg(not(feature = "tokio"))]
+mod threads;
+#[cfg(not(feature = "tokio"))]
+mod timer;
+#[cfg(feature = "tokio")]
+mod tokio;
+
+#[cfg(not(feature = "tokio"))]
+pub use self::threads::Executor;
+#[cfg(not(feature = "tokio"))]
+pub use self::timer::{
+    prepare_fork as prepare_timer_fork, shutdown as shutdown_timer, sleep,
+    ForkGuard as TimerForkGuard,
+};
+#[cfg(feature = "tokio")]
+pub use self::tokio::Executor;
+
+/// Sleep for the given duration without occupying a worker thread. Tokio's
+/// timers can't fail, the result matches the timer thread used without the
+/// `tokio` feature, which may fail to start.
+#[cfg(feature = "tokio")]
+pub async fn sleep(delay: std::time::Duration) -> Result<(), crate::error::Error> {
+    ::tokio::time::sleep(delay).await;
+    Ok(())
+}
+
+/// Stop the threads shared by all executors. Tokio's timers are driven by the
+/// runtime itself, so there is nothing to stop.
+#[cfg(feature = "tokio")]
+pub fn shutdown_timer() {}
+
+/// Lock held across `fork` by the timer. Tokio has no timer thread of its own,
+/// so there is nothing to lock.
+#[cfg(feature = "tokio")]
+pub struct TimerForkGuard;
+
+#[cfg(feature = "tokio")]
+impl TimerForkGuard {
+    pub fn reset(self) {}
+}
+
+#[cfg(feature = "tokio")]
+pub fn prepare_timer_fork() -> TimerForkGuard {
+    TimerForkGuard
+}
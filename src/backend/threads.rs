@@ -0,0 +1,163 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Wake, Waker};
+use std::thread::{self, JoinHandle};
+
+use crate::op::Op;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Default)]
+struct Queue {
+    tasks: VecDeque<Arc<Task>>,
+    shutting_down: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+    active: Mutex<HashMap<u64, Arc<Op>>>,
+}
+
+impl Shared {
+    fn schedule(&self, task: Arc<Task>) {
+        // A task that is already queued will be polled anyway
+        if task.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.queue.lock().unwrap().tasks.push_back(task);
+        self.available.notify_one();
+    }
+}
+
+struct Task {
+    future: Mutex<Option<BoxFuture>>,
+    scheduled: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.shared.clone().schedule(self);
+    }
+}
+
+impl Task {
+    fn run(self: Arc<Self>) {
+        self.scheduled.store(false, Ordering::Release);
+        let waker = Waker::from(self.clone());
+        let mut future = self.future.lock().unwrap();
+        if let Some(fut) = future.as_mut() {
+            if fut
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                *future = None;
+            }
+        }
+    }
+}
+
+/// Pool of worker threads that poll the operations' futures whenever they are
+/// woken up. Sleeping is handled by a separate timer thread, so pending
+/// operations don't occupy a worker.
+pub struct Executor {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Executor {
+    pub fn new(threads: usize, thread_name: &str, stack_size: usize) -> io::Result<Self> {
+        let shared = Arc::new(Shared::default());
+
+        let mut workers = Vec::with_capacity(threads);
+        for i in 0..threads {
+            let mut builder = thread::Builder::new().name(format!("{}-{}", thread_name, i));
+            if stack_size > 0 {
+                builder = builder.stack_size(stack_size);
+            }
+            let worker_shared = shared.clone();
+            match builder.spawn(move || worker(&worker_shared)) {
+                Ok(handle) => workers.push(handle),
+                Err(e) => {
+                    Self { shared, workers }.shutdown();
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(Self { shared, workers })
+    }
+
+    pub fn spawn<F>(&self, op: Arc<Op>, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let id = op.id();
+        self.shared.active.lock().unwrap().insert(id, op);
+
+        let shared = self.shared.clone();
+        let future = async move {
+            task.await;
+            shared.active.lock().unwrap().remove(&id);
+        };
+        self.shared.schedule(Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            scheduled: AtomicBool::new(false),
+            shared: self.shared.clone(),
+        }));
+    }
+
+    /// Cancel every operation that has not yet completed and wait for the
+    /// workers to finish.
+    pub fn shutdown(self) {
+        // Cancelling wakes the operations' tasks, so they are all queued up by
+        // the time the workers are told to exit
+        let active: Vec<_> = self
+            .shared
+            .active
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        for op in active {
+            op.cancel();
+        }
+
+        self.shared.queue.lock().unwrap().shutting_down = true;
+        self.shared.available.notify_all();
+        // A worker stopping its own pool from a completion callback can't join
+        // itself, and exits once the callback returns
+        let current = thread::current().id();
+        for worker in self.workers {
+            if worker.thread().id() != current {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+fn worker(shared: &Shared) {
+    loop {
+        let task = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if let Some(task) = queue.tasks.pop_front() {
+                    break task;
+                }
+                if queue.shutting_down {
+                    return;
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+        task.run();
+    }
+}
@@ -0,0 +1,485 @@
+use std::array;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+
+/// Resolution of the timer. Sleeps complete at most one tick after their
+/// deadline, not counting the time it takes the OS to wake up the timer thread.
+const TICK: Duration = Duration::from_millis(1);
+
+/// Each level of the wheel has 64 slots so a slot's occupancy fits in a `u64`.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+
+/// Six levels cover 2^36 ticks, or a bit more than two years. Entries further
+/// into the future are put in the last level and moved down once it's reached.
+const LEVELS: usize = 6;
+const MAX_TICKS: u64 = 1 << (SLOT_BITS * LEVELS as u32);
+
+/// Handle to an entry in the wheel. The generation makes sure that a handle to
+/// an entry that has fired can't remove an entry that later reused its slot.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Key {
+    index: usize,
+    generation: u64,
+}
+
+struct Entry {
+    generation: u64,
+    /// Tick at which the entry fires.
+    when: u64,
+    /// `None` for free entries.
+    waker: Option<Waker>,
+    /// Level, slot and index in the slot's list, for removing it in constant
+    /// time.
+    level: usize,
+    slot: usize,
+    position: usize,
+}
+
+struct Level {
+    /// Bit `n` is set if slot `n` has any entries.
+    occupied: u64,
+    slots: [Vec<usize>; SLOTS],
+}
+
+impl Level {
+    fn new() -> Self {
+        Self {
+            occupied: 0,
+            slots: array::from_fn(|_| Vec::new()),
+        }
+    }
+}
+
+/// Hierarchical timer wheel. Level `n` has 64 slots that each cover 64^n
+/// ticks. An entry is put in the lowest level whose range covers its deadline
+/// and when a slot of a higher level is reached its entries are moved down to
+/// the lower levels. Inserting, removing and firing entries are all constant
+/// time operations, no matter how many entries there are.
+struct Wheel {
+    /// Ticks since the timer started that have been processed.
+    elapsed: u64,
+    levels: [Level; LEVELS],
+    entries: Vec<Entry>,
+    free: Vec<usize>,
+}
+
+impl Wheel {
+    fn new() -> Self {
+        Self {
+            elapsed: 0,
+            levels: array::from_fn(|_| Level::new()),
+            entries: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Add an entry that fires at the given tick. Returns `None` if the tick
+    /// has already been processed, in which case the entry should fire right
+    /// away.
+    fn insert(&mut self, when: u64, waker: Waker) -> Option<Key> {
+        if when <= self.elapsed {
+            return None;
+        }
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.entries.push(Entry {
+                    generation: 0,
+                    when: 0,
+                    waker: None,
+                    level: 0,
+                    slot: 0,
+                    position: 0,
+                });
+                self.entries.len() - 1
+            }
+        };
+        let entry = &mut self.entries[index];
+        entry.generation += 1;
+        entry.when = when;
+        entry.waker = Some(waker);
+        let key = Key {
+            index,
+            generation: entry.generation,
+        };
+        self.link(index);
+        Some(key)
+    }
+
+    /// Replace the waker of the entry. Returns `false` if it has fired.
+    fn update(&mut self, key: Key, waker: &Waker) -> bool {
+        match self.entries.get_mut(key.index) {
+            Some(entry) if entry.generation == key.generation && entry.waker.is_some() => {
+                entry.waker = Some(waker.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove the entry if it has not yet fired.
+    fn remove(&mut self, key: Key) {
+        let is_live = self
+            .entries
+            .get(key.index)
+            .is_some_and(|e| e.generation == key.generation && e.waker.is_some());
+        if is_live {
+            self.unlink(key.index);
+            self.entries[key.index].waker = None;
+            self.free.push(key.index);
+        }
+    }
+
+    /// Put the entry in the slot that covers its deadline.
+    fn link(&mut self, index: usize) {
+        let when = self.entries[index].when;
+        let level = level_for(self.elapsed, when);
+        let slot = if (self.elapsed ^ when) < MAX_TICKS {
+            slot_for(when, level)
+        } else {
+            // Entries beyond the range of the wheel are put in the last slot
+            // of the current rotation, and moved again once it's reached
+            (slot_for(self.elapsed, level) + SLOTS - 1) % SLOTS
+        };
+        let list = &mut self.levels[level].slots[slot];
+        list.push(index);
+        let entry = &mut self.entries[index];
+        entry.level = level;
+        entry.slot = slot;
+        entry.position = list.len() - 1;
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let Entry {
+            level,
+            slot,
+            position,
+            ..
+        } = self.entries[index];
+        let list = &mut self.levels[level].slots[slot];
+        list.swap_remove(position);
+        if let Some(&moved) = list.get(position) {
+            self.entries[moved].position = position;
+        }
+        if list.is_empty() {
+            self.levels[level].occupied &= !(1 << slot);
+        }
+    }
+
+    /// Return the level and slot that will be processed next along with the
+    /// tick at which it starts.
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        // Entries in lower levels always fire before those in higher levels
+        for (level, l) in self.levels.iter().enumerate() {
+            if l.occupied == 0 {
+                continue;
+            }
+            let slot_ticks = 1u64 << (SLOT_BITS * level as u32);
+            let level_ticks = slot_ticks << SLOT_BITS;
+            let current = slot_for(self.elapsed, level);
+            let offset = l.occupied.rotate_right(current as u32).trailing_zeros() as usize;
+            let slot = (current + offset) % SLOTS;
+
+            let level_start = self.elapsed & !(level_ticks - 1);
+            let mut deadline = level_start + slot as u64 * slot_ticks;
+            if deadline < self.elapsed {
+                deadline += level_ticks;
+            }
+            return Some((level, slot, deadline.max(self.elapsed)));
+        }
+        None
+    }
+
+    /// Process all ticks up to and including `now`, adding the wakers of all
+    /// entries that fired to `fired`.
+    fn advance(&mut self, now: u64, fired: &mut Vec<Waker>) {
+        while let Some((level, slot, deadline)) = self.next_expiration() {
+            if deadline > now {
+                break;
+            }
+            self.elapsed = deadline;
+            self.levels[level].occupied &= !(1 << slot);
+            for index in mem::take(&mut self.levels[level].slots[slot]) {
+                if self.entries[index].when <= self.elapsed {
+                    fired.push(self.entries[index].waker.take().unwrap());
+                    self.free.push(index);
+                } else {
+                    self.link(index);
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+    }
+}
+
+/// Return the level for an entry firing at `when`, which is determined by the
+/// highest bit that differs from the current tick.
+fn level_for(elapsed: u64, when: u64) -> usize {
+    let masked = ((elapsed ^ when) | (SLOTS as u64 - 1)).min(MAX_TICKS - 1);
+    let significant = 63 - masked.leading_zeros();
+    (significant / SLOT_BITS) as usize
+}
+
+fn slot_for(when: u64, level: usize) -> usize {
+    ((when >> (SLOT_BITS * level as u32)) as usize) % SLOTS
+}
+
+struct State {
+    wheel: Wheel,
+    /// Tick that the timer thread will wake up at, if it's waiting for one.
+    next_wakeup: Option<u64>,
+    /// Thread driving the wheel. It's started by the first sleep after the
+    /// timer was created or shut down.
+    thread: Option<JoinHandle<()>>,
+    /// Increased whenever the timer is shut down, which tells the thread that
+    /// was started for the previous generation to exit.
+    generation: u64,
+}
+
+/// Single thread that drives the wheel and wakes up sleeping tasks when their
+/// deadline has passed.
+struct Timer {
+    origin: Instant,
+    state: Mutex<State>,
+    changed: Condvar,
+    /// Held while starting the thread, which is done without holding the
+    /// state lock so the thread failing to start can't poison it.
+    starting: Mutex<()>,
+}
+
+impl Timer {
+    fn get() -> &'static Timer {
+        TIMER.get_or_init(|| Timer {
+            origin: Instant::now(),
+            state: Mutex::new(State {
+                wheel: Wheel::new(),
+                next_wakeup: None,
+                thread: None,
+                generation: 0,
+            }),
+            changed: Condvar::new(),
+            starting: Mutex::new(()),
+        })
+    }
+
+    /// Return the number of whole ticks between the timer's start and the
+    /// given instant, rounded up so deadlines never fire early.
+    fn ticks(&self, instant: Instant, round_up: bool) -> u64 {
+        let elapsed = instant.saturating_duration_since(self.origin);
+        let tick = TICK.as_nanos();
+        let ticks = if round_up {
+            elapsed.as_nanos().div_ceil(tick)
+        } else {
+            elapsed.as_nanos() / tick
+        };
+        ticks.try_into().unwrap_or(u64::MAX)
+    }
+
+    /// Return the instant at which the given tick starts.
+    fn instant(&self, tick: u64) -> Instant {
+        let offset = Duration::from_nanos(tick.saturating_mul(TICK.as_nanos() as u64));
+        // Ticks too far into the future to be represented are never reached
+        self.origin
+            .checked_add(offset)
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(86400 * 365))
+    }
+
+    /// Register a waker that is woken at the deadline, starting the timer
+    /// thread if it isn't running. Returns `None` if the deadline has already
+    /// been reached, and an error if the thread could not be started.
+    fn register(&self, deadline: Instant, waker: &Waker) -> Result<Option<Key>, Error> {
+        let when = self.ticks(deadline, true);
+        let key = {
+            let mut state = self.state.lock().unwrap();
+            let key = match state.wheel.insert(when, waker.clone()) {
+                Some(key) => key,
+                None => return Ok(None),
+            };
+            if state.next_wakeup.is_none_or(|next| when < next) {
+                state.next_wakeup = Some(when);
+                self.changed.notify_all();
+            }
+            key
+        };
+        if let Err(e) = self.start() {
+            self.remove(key);
+            return Err(e);
+        }
+        Ok(Some(key))
+    }
+
+    /// Start the timer thread if it isn't running.
+    fn start(&self) -> Result<(), Error> {
+        let _starting = self.starting.lock().unwrap();
+        let generation = {
+            let state = self.state.lock().unwrap();
+            if state.thread.is_some() {
+                return Ok(());
+            }
+            state.generation
+        };
+        let thread = thread::Builder::new()
+            .name("rust-ffi-timer".into())
+            .spawn(move || Timer::get().run(generation))
+            .map_err(Error::os)?;
+        let mut state = self.state.lock().unwrap();
+        // If the timer was shut down in the meantime, the thread exits by
+        // itself and the next sleep starts another one
+        if state.generation == generation {
+            state.thread = Some(thread);
+        }
+        Ok(())
+    }
+
+    fn update(&self, key: Key, waker: &Waker) -> bool {
+        self.state.lock().unwrap().wheel.update(key, waker)
+    }
+
+    fn remove(&self, key: Key) {
+        self.state.lock().unwrap().wheel.remove(key);
+    }
+
+    fn run(&self, generation: u64) {
+        let mut fired = Vec::new();
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.generation != generation {
+                return;
+            }
+            let now = self.ticks(Instant::now(), false);
+            state.wheel.advance(now, &mut fired);
+            if !fired.is_empty() {
+                // Tasks may register new sleeps when woken, so the lock must
+                // not be held while waking them
+                drop(state);
+                fired.drain(..).for_each(Waker::wake);
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            let next = state.wheel.next_expiration().map(|(_, _, tick)| tick);
+            state.next_wakeup = next;
+            state = match next {
+                Some(tick) => {
+                    let timeout = self.instant(tick).saturating_duration_since(Instant::now());
+                    self.changed.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.changed.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+static TIMER: OnceLock<Timer> = OnceLock::new();
+
+/// Stop the timer thread if it's running and wait for it to exit. The next
+/// sleep starts it again. Sleeps that are still pending don't complete until
+/// then, so this should only be called once they have all been dropped.
+pub fn shutdown() {
+    let timer = match TIMER.get() {
+        Some(timer) => timer,
+        None => return,
+    };
+    let thread = {
+        let mut state = timer.state.lock().unwrap();
+        state.generation += 1;
+        state.next_wakeup = None;
+        timer.changed.notify_all();
+        state.thread.take()
+    };
+    if let Some(thread) = thread {
+        let _ = thread.join();
+    }
+}
+
+/// Locks held across `fork`, so the timer isn't being changed or started
+/// while the process is copied.
+pub struct ForkGuard(Option<(MutexGuard<'static, ()>, MutexGuard<'static, State>)>);
+
+pub fn prepare_fork() -> ForkGuard {
+    ForkGuard(TIMER.get().map(|timer| {
+        // The thread is started while holding the start lock, which then
+        // takes the state lock
+        let starting = timer.starting.lock().unwrap();
+        (starting, timer.state.lock().unwrap())
+    }))
+}
+
+impl ForkGuard {
+    /// Forget the timer thread in the child, since only the thread that
+    /// forked is copied, along with the sleeps of the tasks that were running
+    /// on the parent's workers. The next sleep starts a new thread.
+    pub fn reset(mut self) {
+        if let Some((_, state)) = &mut self.0 {
+            // Dropping the wakers could drop tasks whose sleeps would then try
+            // to take the lock we're holding
+            let wheel = mem::replace(&mut state.wheel, Wheel::new());
+            state.wheel.elapsed = wheel.elapsed;
+            mem::forget(wheel);
+            mem::forget(state.thread.take());
+            state.next_wakeup = None;
+        }
+    }
+}
+
+/// Future returned by `sleep`.
+pub struct Sleep {
+    deadline: Instant,
+    registered: Option<(Key, Waker)>,
+}
+
+impl Future for Sleep {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(Ok(()));
+        }
+
+        let timer = Timer::get();
+        match &self.registered {
+            // The timer only needs to know about us again if we're polled with
+            // a different waker, which the executor never does for the same
+            // task
+            Some((_, waker)) if waker.will_wake(cx.waker()) => {}
+            Some((key, _)) if timer.update(*key, cx.waker()) => {
+                let key = *key;
+                self.registered = Some((key, cx.waker().clone()));
+            }
+            _ => match timer.register(self.deadline, cx.waker()) {
+                Ok(Some(key)) => self.registered = Some((key, cx.waker().clone())),
+                // The deadline passed after it was checked above
+                Ok(None) => return Poll::Ready(Ok(())),
+                Err(e) => return Poll::Ready(Err(e)),
+            },
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        // Cancelled sleeps are removed so they don't linger in the wheel
+        if let Some((key, _)) = self.registered.take() {
+            Timer::get().remove(key);
+        }
+    }
+}
+
+/// Sleep for the given duration without occupying a worker thread. Fails if
+/// the timer thread isn't running and could not be started.
+pub fn sleep(delay: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + delay,
+        registered: None,
+    }
+}
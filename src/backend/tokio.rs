@@ -0,0 +1,55 @@
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::op::Op;
+
+/// Multi-threaded Tokio runtime where operations are tasks, so pending
+/// operations don't occupy any threads.
+pub struct Executor {
+    runtime: Runtime,
+}
+
+impl Executor {
+    pub fn new(threads: usize, thread_name: &str, stack_size: usize) -> io::Result<Self> {
+        let thread_name = thread_name.to_owned();
+        let next_index = AtomicUsize::new(0);
+        let mut builder = Builder::new_multi_thread();
+        builder
+            .worker_threads(threads)
+            .thread_name_fn(move || {
+                let i = next_index.fetch_add(1, Ordering::Relaxed);
+                format!("{}-{}", thread_name, i)
+            })
+            .enable_time();
+        if stack_size > 0 {
+            builder.thread_stack_size(stack_size);
+        }
+        Ok(Self {
+            runtime: builder.build()?,
+        })
+    }
+
+    pub fn spawn<F>(&self, _op: Arc<Op>, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.runtime.spawn(task);
+    }
+
+    /// Stop the runtime. Every task that has not yet completed is dropped,
+    /// which cancels its operation.
+    pub fn shutdown(self) {
+        // Waiting for the workers would block one of them when the runtime is
+        // freed from a completion callback, which Tokio forbids, so they are
+        // left to exit on their own instead
+        if Handle::try_current().is_ok() {
+            self.runtime.shutdown_background();
+        } else {
+            drop(self.runtime);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Copy the Python bindings, the C header and the pkg-config file generated by
+//! the build script to the crate root, where the examples and the tests use
+//! them from. They describe the library built with the same features, so this
+//! must be run with the features the library was built with:
+//!
+//! ```bash
+//! cargo build --features testing
+//! cargo run --features testing --bin gen
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::process;
+
+macro_rules! generated {
+    ($($name:literal),*) => {
+        [$(($name, include_str!(concat!(env!("OUT_DIR"), "/", $name)))),*]
+    };
+}
+
+const FILES: [(&str, &str); 4] = generated!(
+    "async_python_ffi.py",
+    "async_python_ffi.pyi",
+    "async_python_ffi.h",
+    "async_python_ffi.pc"
+);
+
+fn main() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    for (name, contents) in FILES {
+        let path = root.join(name);
+        // Leave unchanged files alone, to avoid touching their mtime
+        if fs::read_to_string(&path).ok().as_deref() == Some(contents) {
+            continue;
+        }
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("failed to write {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::io;
+use std::os::raw::c_int;
+use std::thread;
+use std::time::Duration;
+
+use crate::backend;
+use crate::error::Error;
+#[cfg(feature = "testing")]
+use crate::virtual_clock;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Clocks that absolute deadlines can be given in. The numeric values are
+/// passed over the C ABI and must never change.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RustClock {
+    /// Time since an unspecified point that never jumps, but stops while the
+    /// system is suspended. Same as `time.monotonic_ns()` in Python.
+    Monotonic = 0,
+    /// Same as the monotonic clock, but keeps counting while the system is
+    /// suspended.
+    Boottime = 1,
+    /// Wall-clock time since the Unix epoch, which jumps when the system's
+    /// time is changed. Same as `time.time_ns()` in Python.
+    Realtime = 2,
+}
+
+impl RustClock {
+    pub fn from_raw(clock: c_int) -> Result<Self, Error> {
+        match clock {
+            0 => Ok(Self::Monotonic),
+            1 => Ok(Self::Boottime),
+            2 => Ok(Self::Realtime),
+            _ => Err(Error::invalid_argument(format!("unknown clock {}", clock))),
+        }
+    }
+
+    pub fn id(self) -> libc::clockid_t {
+        match self {
+            Self::Monotonic => libc::CLOCK_MONOTONIC,
+            Self::Boottime => libc::CLOCK_BOOTTIME,
+            Self::Realtime => libc::CLOCK_REALTIME,
+        }
+    }
+
+    /// Return the current time of the clock in nanoseconds, which is the
+    /// virtual clock's time if it's enabled.
+    pub fn now(self) -> Result<i64, Error> {
+        #[cfg(feature = "testing")]
+        if let Some(now) = virtual_clock::now(self) {
+            return Ok(now);
+        }
+        self.real_now()
+    }
+
+    /// Return the current time of the OS's clock in nanoseconds.
+    pub fn real_now(self) -> Result<i64, Error> {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        if unsafe { libc::clock_gettime(self.id(), &mut ts) } < 0 {
+            return Err(Error::os(io::Error::last_os_error()));
+        }
+        Ok(ts
+            .tv_sec
+            .saturating_mul(NANOS_PER_SEC)
+            .saturating_add(ts.tv_nsec))
+    }
+
+    /// Return how long it is until the clock reaches `deadline_ns`, which is
+    /// zero if it has already been reached.
+    pub fn until(self, deadline_ns: i64) -> Result<Duration, Error> {
+        let remaining = i128::from(deadline_ns) - i128::from(self.now()?);
+        Ok(Duration::from_nanos(
+            remaining.clamp(0, u64::MAX.into()) as u64
+        ))
+    }
+}
+
+/// Convert nanoseconds since a clock's epoch to a `timespec`.
+pub fn timespec(ns: i64) -> libc::timespec {
+    libc::timespec {
+        tv_sec: ns.div_euclid(NANOS_PER_SEC) as libc::time_t,
+        tv_nsec: ns.rem_euclid(NANOS_PER_SEC) as libc::c_long,
+    }
+}
+
+/// Sleep for `delay` without occupying a worker thread. The sleep follows the
+/// virtual clock if it was enabled when the sleep started.
+pub async fn sleep(delay: Duration) -> Result<(), Error> {
+    #[cfg(feature = "testing")]
+    if let Some(sleep) = virtual_clock::sleep(delay) {
+        sleep.await;
+        return Ok(());
+    }
+    backend::sleep(delay).await
+}
+
+/// Block the calling thread for `delay`, following the virtual clock if it's
+/// enabled.
+pub fn block_for(delay: Duration) {
+    #[cfg(feature = "testing")]
+    if virtual_clock::block_for(delay) {
+        return;
+    }
+    thread::sleep(delay);
+}
@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::raw::c_int;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::Error;
+use crate::fork;
+use crate::panic::catch_panic;
+
+/// Record describing a finished operation. The layout is part of the ABI.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RustCompletion {
+    pub op_id: u64,
+    pub status: c_int,
+    pub result: i64,
+}
+
+/// Queue of completion records paired with an eventfd that is readable
+/// whenever the queue is non-empty.
+struct CompletionQueue {
+    fd: OwnedFd,
+    records: Mutex<VecDeque<RustCompletion>>,
+}
+
+impl CompletionQueue {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            records: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn signal(&self) {
+        // Writing can only fail if the counter would overflow, in which case
+        // the fd is already readable
+        let one = 1u64;
+        unsafe { libc::write(self.fd.as_raw_fd(), (&one as *const u64).cast(), 8) };
+    }
+
+    fn clear(&self) {
+        let mut counter = 0u64;
+        unsafe { libc::read(self.fd.as_raw_fd(), (&mut counter as *mut u64).cast(), 8) };
+    }
+
+    fn push(&self, record: RustCompletion) {
+        self.records.lock().unwrap().push_back(record);
+        self.signal();
+    }
+
+    /// # Safety
+    ///
+    /// `buf` must be valid for writing `n` records.
+    unsafe fn drain(&self, buf: *mut RustCompletion, n: usize) -> usize {
+        let mut records = self.records.lock().unwrap();
+        self.clear();
+
+        let n = n.min(records.len());
+        for (i, record) in records.drain(..n).enumerate() {
+            buf.add(i).write(record);
+        }
+
+        // Make sure the fd stays readable if the caller's buffer was too small
+        // to fit everything
+        if !records.is_empty() {
+            self.signal();
+        }
+        n
+    }
+}
+
+static QUEUE: Mutex<Option<Arc<CompletionQueue>>> = Mutex::new(None);
+
+fn queue() -> Result<Arc<CompletionQueue>, Error> {
+    fork::register();
+    let mut queue = QUEUE.lock().unwrap();
+    match &*queue {
+        Some(queue) => Ok(queue.clone()),
+        None => {
+            let new_queue = Arc::new(CompletionQueue::new().map_err(Error::os)?);
+            *queue = Some(new_queue.clone());
+            Ok(new_queue)
+        }
+    }
+}
+
+/// Lock held across `fork`, so the queue isn't being created while the
+/// process is copied.
+pub struct ForkGuard(MutexGuard<'static, Option<Arc<CompletionQueue>>>);
+
+pub fn prepare_fork() -> ForkGuard {
+    ForkGuard(QUEUE.lock().unwrap())
+}
+
+impl ForkGuard {
+    /// Drop the queue in the child, since its eventfd is shared with the
+    /// parent. The next use of the queue creates a new one.
+    pub fn reset(mut self) {
+        self.0.take();
+    }
+}
+
+/// Create the completion queue if it doesn't exist yet.
+pub fn init() -> Result<(), Error> {
+    queue().map(|_| ())
+}
+
+/// Post a completion record to the library's completion queue.
+pub fn push(record: RustCompletion) {
+    if let Ok(queue) = queue() {
+        queue.push(record);
+    }
+}
+
+/// Return the file descriptor of the library's completion queue, or `-1` if
+/// it could not be created in which case the error is reported on the calling
+/// thread. The fd becomes readable when there are completion
+/// records available for `rust_cq_drain`. It's owned by the library and must
+/// not be closed by the caller. A process created by `fork` gets a queue of
+/// its own, so the child must call this again rather than use the parent's fd.
+#[no_mangle]
+pub extern "C" fn rust_cq_fd() -> c_int {
+    catch_panic(-1, || match queue() {
+        Ok(queue) => queue.fd.as_raw_fd(),
+        Err(e) => {
+            e.set_last();
+            -1
+        }
+    })
+}
+
+/// Pop up to `n` completion records into `buf` and return how many were
+/// written, or `-1` if the completion queue is not available in which case the
+/// error is reported on the calling thread. This never blocks.
+///
+/// # Safety
+///
+/// `buf` must point to at least `n` writable `RustCompletion` records.
+#[no_mangle]
+pub unsafe extern "C" fn rust_cq_drain(buf: *mut RustCompletion, n: usize) -> c_int {
+    catch_panic(-1, || {
+        let queue = match queue() {
+            Ok(queue) => queue,
+            Err(e) => {
+                e.set_last();
+                return -1;
+            }
+        };
+        if buf.is_null() || n == 0 {
+            return 0;
+        }
+        let n = n.min(c_int::MAX as usize);
+        queue.drain(buf, n) as c_int
+    })
+}
@@ -0,0 +1,209 @@
+use std::future::Future;
+use std::os::raw::c_int;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::abi;
+use crate::clock::RustClock;
+use crate::error::{self, Error, STATUS_OK};
+use crate::handle::Table;
+use crate::op::{self, Op, OpValue};
+use crate::panic::{catch_panic, catch_panic_status};
+use crate::pool::{self, Pool};
+use crate::runtime::RustRuntime;
+use crate::scope::{RustScope, Scope};
+use crate::sleep::wait_until;
+
+/// Configuration for a context. Zero or null fields are left unset.
+#[repr(C)]
+pub struct RustCtxConfig {
+    /// Must be set to `sizeof(RustCtxConfig)`.
+    pub struct_size: usize,
+    /// Time in nanoseconds on the monotonic clock by which operations started
+    /// with the context must have completed, or `0` for no deadline.
+    pub deadline_ns: i64,
+    /// Scope that operations started with the context are added to, so
+    /// cancelling it cancels them.
+    pub scope: *const RustScope,
+    /// Value identifying the context, like the id of the request it belongs
+    /// to. It's included in the messages of the errors it causes.
+    pub tag: u64,
+    /// Runtime that operations started with the context run on, or null for
+    /// the global worker pool.
+    pub runtime: *const RustRuntime,
+}
+
+/// Deadline, cancellation scope, tag and runtime that operations are started
+/// with.
+#[derive(Clone, Default)]
+pub struct Ctx {
+    deadline_ns: Option<i64>,
+    scope: Option<Arc<Scope>>,
+    tag: u64,
+    pool: Option<Arc<Pool>>,
+}
+
+/// Opaque handle to a context. It must be released using `rust_ctx_free`.
+pub struct RustCtx {
+    _private: [u8; 0],
+}
+
+pub static CTXS: Table<RustCtx, Ctx> = Table::new("context");
+
+/// Future that resolves to the wrapped future's output, unless the context's
+/// deadline passes first.
+struct Deadline<F> {
+    future: Pin<Box<F>>,
+    expired: Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>,
+    tag: u64,
+}
+
+impl<F, T> Future for Deadline<F>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // The deadline is checked first so an operation that is polled after
+        // it has passed fails even if it could complete
+        if let Poll::Ready(result) = self.expired.as_mut().poll(cx) {
+            let e = match result {
+                Ok(()) => Error::timeout(format!("deadline of context {} exceeded", self.tag)),
+                Err(e) => e,
+            };
+            return Poll::Ready(Err(e));
+        }
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Run the given future on the context's worker pool like `op::spawn`, failing
+/// it with `Timeout` if the context's deadline passes first and adding the
+/// operation to the context's scope. A null context imposes no limits and uses
+/// the global pool.
+pub fn spawn<F, T>(ctx: *const RustCtx, op: Arc<Op>, future: F) -> Arc<Op>
+where
+    F: Future<Output = Result<T, Error>> + Send + 'static,
+    T: OpValue,
+{
+    let ctx = if ctx.is_null() {
+        Ctx::default()
+    } else {
+        match CTXS.get(ctx) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                op.complete(Err(e));
+                return op;
+            }
+        }
+    };
+    if let Some(scope) = &ctx.scope {
+        scope.add(&op);
+    }
+    let pool = ctx.pool.as_deref().unwrap_or(pool::global());
+    match ctx.deadline_ns {
+        Some(deadline_ns) => op::spawn(
+            pool,
+            op,
+            Deadline {
+                future: Box::pin(future),
+                expired: Box::pin(wait_until(RustClock::Monotonic, deadline_ns)),
+                tag: ctx.tag,
+            },
+        ),
+        None => op::spawn(pool, op, future),
+    }
+}
+
+/// Create a context that operations can be started with. Operations that are
+/// still running at the context's deadline fail with the `Timeout` status,
+/// those started with a scope are cancelled along with it and those started
+/// with a runtime run on its worker pool. A null `config` creates a context
+/// without any limits that uses the global pool.
+///
+/// Returns null on failure, in which case the error is reported on the
+/// calling thread. The handle must be released using `rust_ctx_free`, which
+/// doesn't affect operations that have already been started with it.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid `RustCtxConfig`.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ctx_new(config: *const RustCtxConfig) -> *mut RustCtx {
+    catch_panic(ptr::null_mut(), || {
+        // Only the size is read until it's known that the caller's structure
+        // has the same layout
+        if !config.is_null() {
+            let struct_size = (*config).struct_size;
+            if let Err(e) = abi::check_struct_size::<RustCtxConfig>(struct_size, "RustCtxConfig") {
+                e.set_last();
+                return ptr::null_mut();
+            }
+        }
+
+        let ctx = match config.as_ref() {
+            Some(config) => {
+                let scope = if config.scope.is_null() {
+                    None
+                } else {
+                    match RustScope::get(config.scope) {
+                        Ok(scope) => Some(scope),
+                        Err(e) => {
+                            e.set_last();
+                            return ptr::null_mut();
+                        }
+                    }
+                };
+                let pool = if config.runtime.is_null() {
+                    None
+                } else {
+                    match RustRuntime::get(config.runtime) {
+                        Ok(pool) => Some(pool),
+                        Err(e) => {
+                            e.set_last();
+                            return ptr::null_mut();
+                        }
+                    }
+                };
+                Ctx {
+                    deadline_ns: Some(config.deadline_ns).filter(|&d| d != 0),
+                    scope,
+                    tag: config.tag,
+                    pool,
+                }
+            }
+            None => Ctx::default(),
+        };
+        CTXS.insert(ctx)
+    })
+}
+
+/// Return the tag the context was created with. Returns `0` if the handle is
+/// null or has been freed, in which case the error is reported on the calling
+/// thread.
+#[no_mangle]
+pub extern "C" fn rust_ctx_tag(ctx: *const RustCtx) -> u64 {
+    catch_panic(0, || match CTXS.get(ctx) {
+        Ok(ctx) => ctx.tag,
+        Err(e) => {
+            e.set_last();
+            0
+        }
+    })
+}
+
+/// Release the context. Operations started with it keep its deadline and
+/// scope. Returns `0` on success or if the handle is null, and
+/// `InvalidHandle` if it has already been freed.
+#[no_mangle]
+pub extern "C" fn rust_ctx_free(ctx: *mut RustCtx) -> c_int {
+    catch_panic_status(|| {
+        if ctx.is_null() {
+            return STATUS_OK;
+        }
+        error::status(CTXS.remove(ctx))
+    })
+}
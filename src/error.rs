@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use crate::panic::{catch_panic, catch_panic_status};
+
+/// Status returned when an operation has not yet completed.
+pub const STATUS_PENDING: c_int = -1;
+
+/// Status returned on success.
+pub const STATUS_OK: c_int = 0;
+
+/// Kinds of errors the library can report. The numeric values are returned as
+/// statuses over the C ABI and must never change.
+// Codes are part of the ABI even if nothing reports them yet
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// An argument was out of range or otherwise invalid.
+    InvalidArgument = 1,
+    /// The operation was cancelled before it completed.
+    Cancelled = 2,
+    /// The operation did not complete before its deadline.
+    Timeout = 3,
+    /// The library panicked while running the operation.
+    Panic = 4,
+    /// A system call failed, for example when spawning threads.
+    Os = 5,
+    /// The library is not in a state where the call is allowed.
+    InvalidState = 6,
+    /// The caller was built for another version of the library's ABI.
+    AbiMismatch = 7,
+    /// The operation was woken up before it completed. Unlike `Cancelled`
+    /// it reports how much was left, see the function for details.
+    Interrupted = 8,
+    /// A handle was used after it had been freed, or was never returned by
+    /// the library in the first place.
+    InvalidHandle = 9,
+}
+
+#[derive(Clone, Debug)]
+pub struct Error {
+    code: ErrorCode,
+    message: String,
+    /// Value reported along with the error, like how much of an interrupted
+    /// operation was left.
+    value: Option<i64>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            value: None,
+        }
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidArgument, message)
+    }
+
+    pub fn cancelled() -> Self {
+        Self::new(ErrorCode::Cancelled, "operation was cancelled")
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Timeout, message)
+    }
+
+    pub fn os(err: std::io::Error) -> Self {
+        Self::new(ErrorCode::Os, err.to_string())
+    }
+
+    pub fn invalid_state(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidState, message)
+    }
+
+    pub fn abi_mismatch(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::AbiMismatch, message)
+    }
+
+    pub fn invalid_handle(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidHandle, message)
+    }
+
+    pub fn interrupted(remaining: i64) -> Self {
+        Self {
+            value: Some(remaining),
+            ..Self::new(ErrorCode::Interrupted, "operation was interrupted")
+        }
+    }
+
+    pub fn value(&self) -> Option<i64> {
+        self.value
+    }
+
+    /// Store this as the calling thread's last error and return the status
+    /// code to return over the C ABI.
+    pub fn set_last(self) -> c_int {
+        let code = self.code as c_int;
+        LAST_ERROR.with(|last| *last.borrow_mut() = Some(self));
+        code
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<Error>> = const { RefCell::new(None) };
+}
+
+/// Convert the result into a status code, storing the error as the calling
+/// thread's last error if there is one.
+pub fn status<T>(result: Result<T, Error>) -> c_int {
+    match result {
+        Ok(_) => STATUS_OK,
+        Err(e) => e.set_last(),
+    }
+}
+
+/// Return the code of the last error reported on the calling thread, or `0` if
+/// no error has been reported. Like `errno` it's not reset by successful calls,
+/// so it's only meaningful directly after a call reported an error.
+///
+/// Functions that return the status of an operation, like `rust_op_poll` and
+/// `rust_op_wait`, also report the operation's error on the calling thread.
+#[no_mangle]
+pub extern "C" fn rust_last_error_code() -> c_int {
+    catch_panic_status(|| {
+        LAST_ERROR.with(|last| {
+            last.borrow()
+                .as_ref()
+                .map_or(STATUS_OK, |e| e.code as c_int)
+        })
+    })
+}
+
+/// Copy the message of the last error reported on the calling thread into
+/// `buf` as a nul-terminated string, truncating it if it doesn't fit. Returns
+/// the length of the full message excluding the nul terminator, so a return
+/// value of `len` or more means the message was truncated. The message is
+/// empty if no error has been reported.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for writing `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rust_last_error_message(buf: *mut c_char, len: usize) -> usize {
+    catch_panic(0, || {
+        LAST_ERROR.with(|last| {
+            let last = last.borrow();
+            let message = last.as_ref().map_or("", |e| e.message.as_str());
+            if !buf.is_null() && len > 0 {
+                let n = message.len().min(len - 1);
+                ptr::copy_nonoverlapping(message.as_ptr().cast(), buf, n);
+                buf.add(n).write(0);
+            }
+            message.len()
+        })
+    })
+}
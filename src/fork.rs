@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+
+use crate::backend::{self, TimerForkGuard};
+use crate::cq;
+use crate::ctx::{Ctx, CTXS};
+use crate::handle;
+use crate::interval::{self, Interval, INTERVALS};
+use crate::op::{self, Op, OPS};
+use crate::panic::catch_panic;
+use crate::pool::{self, Pool};
+use crate::runtime::RUNTIMES;
+use crate::scope::{self, Scope, SCOPES};
+#[cfg(feature = "testing")]
+use crate::virtual_clock;
+
+/// Lock on a mutex inside a value shared through an `Arc`, which keeps the
+/// value alive for as long as the lock is held.
+pub struct ArcGuard<T: 'static, U: 'static> {
+    // Declared before the value so it's dropped first
+    guard: MutexGuard<'static, U>,
+    _owner: Arc<T>,
+}
+
+impl<T, U> ArcGuard<T, U> {
+    pub fn lock(owner: Arc<T>, mutex: fn(&T) -> &Mutex<U>) -> Self {
+        // The mutex is borrowed from the value, which doesn't move and isn't
+        // dropped until after the guard
+        let mutex = unsafe { &*(mutex(&owner) as *const Mutex<U>) };
+        Self {
+            guard: mutex.lock().unwrap(),
+            _owner: owner,
+        }
+    }
+}
+
+impl<T, U> Deref for ArcGuard<T, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        &self.guard
+    }
+}
+
+impl<T, U> DerefMut for ArcGuard<T, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        &mut self.guard
+    }
+}
+
+/// Every lock on the library's global state, held by the thread that forks
+/// from just before until just after the process is copied. Without them the
+/// child could inherit a lock held by a thread that doesn't exist in the
+/// child, and deadlock the first time it's used.
+struct Guards {
+    _runtimes: handle::ForkGuard<Arc<Pool>>,
+    _ctxs: handle::ForkGuard<Ctx>,
+    _scopes: handle::ForkGuard<Arc<Scope>>,
+    _intervals: handle::ForkGuard<Arc<Interval>>,
+    _ops: handle::ForkGuard<Arc<Op>>,
+    pools: pool::ForkGuard,
+    _scope_states: scope::ForkGuard,
+    op_states: op::ForkGuard,
+    _interval_states: interval::ForkGuard,
+    queue: cq::ForkGuard,
+    timer: TimerForkGuard,
+    #[cfg(feature = "testing")]
+    virtual_clock: virtual_clock::ForkGuard,
+}
+
+thread_local! {
+    static GUARDS: RefCell<Option<Guards>> = const { RefCell::new(None) };
+}
+
+extern "C" fn prepare() {
+    catch_panic((), || {
+        // The locks are taken in this order, which is the order they're
+        // nested in everywhere else: the handle tables, which are never held
+        // while taking another lock, then the worker pools, the scopes,
+        // which check their operations while locked, the operations, whose
+        // waker and cancellation error may take any of the remaining locks,
+        // and finally the intervals, the completion queue, the timer and the
+        // virtual clock, none of which are held while taking another lock.
+        let runtimes = RUNTIMES.prepare_fork();
+        let ctxs = CTXS.prepare_fork();
+        let scopes = SCOPES.prepare_fork();
+        let intervals = INTERVALS.prepare_fork();
+        let ops = OPS.prepare_fork();
+        let pools = pool::prepare_fork(runtimes.values());
+        let scope_states = scope::prepare_fork(scopes.values());
+        // Operations that were freed may still be cancelled through a scope
+        let op_states = op::prepare_fork(ops.values().cloned().chain(scope_states.ops()));
+        let interval_states = interval::prepare_fork(intervals.values());
+        let guards = Guards {
+            _runtimes: runtimes,
+            _ctxs: ctxs,
+            _scopes: scopes,
+            _intervals: intervals,
+            _ops: ops,
+            pools,
+            _scope_states: scope_states,
+            op_states,
+            _interval_states: interval_states,
+            queue: cq::prepare_fork(),
+            timer: backend::prepare_timer_fork(),
+            #[cfg(feature = "testing")]
+            virtual_clock: virtual_clock::prepare_fork(),
+        };
+        GUARDS.with(|g| *g.borrow_mut() = Some(guards));
+    })
+}
+
+extern "C" fn parent() {
+    catch_panic((), || {
+        GUARDS.with(|g| g.borrow_mut().take());
+    })
+}
+
+extern "C" fn child() {
+    catch_panic((), || {
+        let guards = match GUARDS.with(|g| g.borrow_mut().take()) {
+            Some(guards) => guards,
+            None => return,
+        };
+        // The tasks of the operations that were running only exist in the
+        // parent, so waiting for them in the child would block forever
+        guards.op_states.reset();
+        guards.pools.reset();
+        guards.queue.reset();
+        guards.timer.reset();
+        #[cfg(feature = "testing")]
+        guards.virtual_clock.reset();
+    })
+}
+
+/// Register the handlers that make the library usable in a child process
+/// after `fork`. The child gets new worker pools, a new timer thread and a
+/// new completion queue when it first needs them, and operations that were
+/// running in the parent complete with `Cancelled` in the child. This is
+/// called whenever global state that needs resetting is created.
+pub fn register() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        libc::pthread_atfork(Some(prepare), Some(parent), Some(child));
+    });
+}
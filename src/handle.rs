@@ -0,0 +1,155 @@
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::Error;
+
+/// Handles are split into the index of their slot in the low half and the
+/// slot's generation in the high half.
+const INDEX_BITS: u32 = usize::BITS / 2;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+const MAX_GENERATION: usize = usize::MAX >> INDEX_BITS;
+
+struct Slot<T> {
+    /// Increased whenever the slot is freed, so handles to the value that was
+    /// in it before no longer match. It starts at one so a handle is never
+    /// zero, which is what the caller sees as a null pointer.
+    generation: usize,
+    value: Option<T>,
+}
+
+struct Slots<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+}
+
+/// Table of the values that handles given to the caller refer to. A handle
+/// is the index of the value's slot along with the slot's generation, so
+/// using a handle after it has been freed, including freeing it twice, is
+/// reported as an error rather than touching freed memory.
+///
+/// Handles are passed over the C ABI as pointers to the opaque type `H`, but
+/// they are never dereferenced.
+pub struct Table<H, T> {
+    /// What the handles refer to, for error messages.
+    name: &'static str,
+    slots: Mutex<Slots<T>>,
+    handle: PhantomData<fn() -> H>,
+}
+
+impl<H, T: Clone> Table<H, T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            slots: Mutex::new(Slots {
+                slots: Vec::new(),
+                free: Vec::new(),
+            }),
+            handle: PhantomData,
+        }
+    }
+
+    /// Store the value and return a handle to it.
+    pub fn insert(&self, value: T) -> *mut H {
+        let mut slots = self.slots.lock().unwrap();
+        let index = match slots.free.pop() {
+            Some(index) => index,
+            None => {
+                assert!(
+                    slots.slots.len() <= INDEX_MASK,
+                    "too many {} handles",
+                    self.name
+                );
+                slots.slots.push(Slot {
+                    generation: 1,
+                    value: None,
+                });
+                slots.slots.len() - 1
+            }
+        };
+        let slot = &mut slots.slots[index];
+        slot.value = Some(value);
+        ptr::without_provenance_mut(slot.generation << INDEX_BITS | index)
+    }
+
+    /// Return the value the handle refers to.
+    pub fn get(&self, handle: *const H) -> Result<T, Error> {
+        let mut slots = self.slots.lock().unwrap();
+        self.slot(&mut slots, handle)
+            .map(|slot| slot.value.clone().unwrap())
+    }
+
+    /// Free the handle and return the value it referred to.
+    pub fn remove(&self, handle: *mut H) -> Result<T, Error> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = self.slot(&mut slots, handle)?;
+        let value = slot.value.take().unwrap();
+        // Slots whose generation would wrap around are never reused, since
+        // stale handles to them would become valid again
+        slot.generation += 1;
+        let reusable = slot.generation <= MAX_GENERATION;
+        if reusable {
+            slots.free.push(handle.addr() & INDEX_MASK);
+        }
+        Ok(value)
+    }
+
+    /// Return the values of the handles that have not yet been freed.
+    pub fn values(&self) -> Vec<T> {
+        let slots = self.slots.lock().unwrap();
+        slots
+            .slots
+            .iter()
+            .filter_map(|slot| slot.value.clone())
+            .collect()
+    }
+
+    /// Return the number of handles that have not yet been freed.
+    pub fn len(&self) -> usize {
+        let slots = self.slots.lock().unwrap();
+        slots
+            .slots
+            .iter()
+            .filter(|slot| slot.value.is_some())
+            .count()
+    }
+
+    /// Lock the table for the duration of a `fork`, so it's consistent in the
+    /// child.
+    pub fn prepare_fork(&'static self) -> ForkGuard<T> {
+        ForkGuard(self.slots.lock().unwrap())
+    }
+
+    fn slot<'a>(
+        &self,
+        slots: &'a mut Slots<T>,
+        handle: *const H,
+    ) -> Result<&'a mut Slot<T>, Error> {
+        let handle = handle.addr();
+        if handle == 0 {
+            return Err(Error::invalid_argument(format!(
+                "{} handle is null",
+                self.name
+            )));
+        }
+        match slots.slots.get_mut(handle & INDEX_MASK) {
+            Some(slot) if slot.generation == handle >> INDEX_BITS && slot.value.is_some() => {
+                Ok(slot)
+            }
+            _ => Err(Error::invalid_handle(format!(
+                "{} handle is invalid or has already been freed",
+                self.name,
+            ))),
+        }
+    }
+}
+
+/// Lock held across `fork` by `Table::prepare_fork`.
+pub struct ForkGuard<T: 'static>(MutexGuard<'static, Slots<T>>);
+
+impl<T> ForkGuard<T> {
+    /// Return the values of the handles that have not been freed.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.0.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+}
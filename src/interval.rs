@@ -0,0 +1,269 @@
+use std::future::Future;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use crate::clock::RustClock;
+use crate::ctx::{self, RustCtx};
+use crate::error::{self, Error, STATUS_OK};
+use crate::fork::ArcGuard;
+use crate::handle::Table;
+use crate::op::{self, Op, RustCallback, RustOp};
+use crate::panic::{catch_panic, catch_panic_status};
+use crate::sleep;
+
+/// Ticks that complete less than this late don't count as missed, so the
+/// schedule isn't shifted by ordinary scheduling delays. Same as Tokio.
+const MISSED_TICK_THRESHOLD_NS: i64 = 5_000_000;
+
+/// What an interval does when a tick is consumed so late that one or more
+/// ticks were missed. The numeric values are passed over the C ABI and must
+/// never change.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RustMissedTick {
+    /// Complete the missed ticks right away until the interval has caught up
+    /// with the original schedule.
+    Burst = 0,
+    /// Schedule the next tick one period after the late one was consumed, so
+    /// the whole schedule is shifted by the delay.
+    Delay = 1,
+    /// Drop the missed ticks and continue with the next tick of the original
+    /// schedule that is still in the future.
+    Skip = 2,
+}
+
+impl RustMissedTick {
+    fn from_raw(missed_tick: c_int) -> Result<Self, Error> {
+        match missed_tick {
+            0 => Ok(Self::Burst),
+            1 => Ok(Self::Delay),
+            2 => Ok(Self::Skip),
+            _ => Err(Error::invalid_argument(format!(
+                "unknown missed tick behaviour {}",
+                missed_tick
+            ))),
+        }
+    }
+
+    /// Return when the tick after the one scheduled at `tick` is due, given
+    /// that it was consumed at `now`.
+    fn next(self, tick: i64, now: i64, period: i64) -> i64 {
+        if now - tick <= MISSED_TICK_THRESHOLD_NS {
+            return tick.saturating_add(period);
+        }
+        match self {
+            Self::Burst => tick.saturating_add(period),
+            Self::Delay => now.saturating_add(period),
+            Self::Skip => now.saturating_add(period - (now - tick) % period),
+        }
+    }
+}
+
+struct State {
+    /// When the next tick is due on the monotonic clock.
+    next: i64,
+    /// Whether a tick is being waited for.
+    pending: bool,
+}
+
+pub struct Interval {
+    period: i64,
+    missed_tick: RustMissedTick,
+    state: Mutex<State>,
+}
+
+/// Clears the interval's pending flag when the tick it's waiting for has
+/// completed or was cancelled.
+struct Pending(Arc<Interval>);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().pending = false;
+    }
+}
+
+impl Interval {
+    /// Wait for the next tick and return when it was scheduled for. The
+    /// interval must already be marked as pending, which is cleared once the
+    /// returned future completes or is dropped.
+    fn tick(self: Arc<Self>) -> impl Future<Output = Result<i64, Error>> {
+        let pending = Pending(self);
+        async move {
+            let interval = &pending.0;
+            let tick = interval.state.lock().unwrap().next;
+            sleep::wait_until(RustClock::Monotonic, tick).await?;
+
+            // The tick only counts as consumed once it has completed, so
+            // cancelling the wait leaves the schedule untouched
+            let now = RustClock::Monotonic.now()?;
+            let next = interval.missed_tick.next(tick, now, interval.period);
+            interval.state.lock().unwrap().next = next;
+            Ok(tick)
+        }
+    }
+}
+
+/// Locks held on the intervals across `fork`.
+pub struct ForkGuard {
+    _states: Vec<ArcGuard<Interval, State>>,
+}
+
+pub fn prepare_fork<'a>(intervals: impl Iterator<Item = &'a Arc<Interval>>) -> ForkGuard {
+    ForkGuard {
+        _states: intervals
+            .map(|interval| ArcGuard::lock(interval.clone(), |interval| &interval.state))
+            .collect(),
+    }
+}
+
+/// Opaque handle to an interval timer. It must be released using
+/// `rust_interval_free`.
+pub struct RustInterval {
+    _private: [u8; 0],
+}
+
+pub static INTERVALS: Table<RustInterval, Arc<Interval>> = Table::new("interval");
+
+fn new_interval(period_ns: i64, phase_ns: i64, missed_tick: c_int) -> Result<Interval, Error> {
+    if period_ns <= 0 {
+        return Err(Error::invalid_argument(format!(
+            "period must be positive, got {}",
+            period_ns
+        )));
+    }
+    if phase_ns < 0 {
+        return Err(Error::invalid_argument(format!(
+            "phase must not be negative, got {}",
+            phase_ns
+        )));
+    }
+    Ok(Interval {
+        period: period_ns,
+        missed_tick: RustMissedTick::from_raw(missed_tick)?,
+        state: Mutex::new(State {
+            next: RustClock::Monotonic.now()?.saturating_add(phase_ns),
+            pending: false,
+        }),
+    })
+}
+
+/// Create an interval timer that ticks every `period_ns` nanoseconds, with the
+/// first tick `phase_ns` nanoseconds from now. The ticks follow a fixed
+/// schedule, so they don't drift no matter how long it takes to handle each
+/// of them. `missed_tick` is one of `RustMissedTick` and decides what happens
+/// when ticks are missed because the next one was requested too late.
+///
+/// Returns null on failure, in which case the error is reported on the
+/// calling thread. The handle must be released using `rust_interval_free`.
+#[no_mangle]
+pub extern "C" fn rust_interval_new(
+    period_ns: i64,
+    phase_ns: i64,
+    missed_tick: c_int,
+) -> *mut RustInterval {
+    catch_panic(ptr::null_mut(), || {
+        match new_interval(period_ns, phase_ns, missed_tick) {
+            Ok(interval) => INTERVALS.insert(Arc::new(interval)),
+            Err(e) => {
+                e.set_last();
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Start waiting for the next tick of the interval for the given operation.
+fn next(ctx: *const RustCtx, interval: *const RustInterval, op: Arc<Op>) -> Arc<Op> {
+    let interval = match INTERVALS.get(interval) {
+        Ok(interval) => interval,
+        Err(e) => {
+            op.complete(Err(e));
+            return op;
+        }
+    };
+    let already_pending = std::mem::replace(&mut interval.state.lock().unwrap().pending, true);
+    if already_pending {
+        op.complete(Err(Error::invalid_state(
+            "interval is already waiting for a tick",
+        )));
+        return op;
+    }
+    ctx::spawn(ctx, op, interval.tick())
+}
+
+/// Start waiting for the next tick of the interval with the deadline and scope
+/// of `ctx`, which may be null. The operation's result is the time the tick
+/// was scheduled for in nanoseconds on the monotonic clock, which can be read
+/// using `rust_interval_next_result`. Only one tick can be waited for at a
+/// time, and cancelling the wait doesn't consume the tick.
+///
+/// The returned handle must be freed using `rust_op_free`, which cancels the
+/// wait if it's still running.
+#[no_mangle]
+pub extern "C" fn rust_interval_next(
+    ctx: *const RustCtx,
+    interval: *const RustInterval,
+) -> *mut RustOp {
+    catch_panic(ptr::null_mut(), || {
+        RustOp::into_raw(next(ctx, interval, Op::new()))
+    })
+}
+
+/// Same as `rust_interval_next`, but calls `callback` with `user_data` and the
+/// status from a worker thread once the tick is due. Returns null with
+/// `InvalidArgument` if `callback` is null.
+#[no_mangle]
+pub extern "C" fn rust_interval_next_callback(
+    ctx: *const RustCtx,
+    interval: *const RustInterval,
+    callback: RustCallback,
+    user_data: *mut c_void,
+) -> *mut RustOp {
+    catch_panic(ptr::null_mut(), || {
+        match Op::with_callback(callback, user_data) {
+            Ok(op) => RustOp::into_raw(next(ctx, interval, op)),
+            Err(e) => {
+                e.set_last();
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Same as `rust_interval_next`, but posts a record to the completion queue
+/// once the tick is due. The record's result is the time of the tick.
+#[no_mangle]
+pub extern "C" fn rust_interval_next_submit(
+    ctx: *const RustCtx,
+    interval: *const RustInterval,
+) -> *mut RustOp {
+    catch_panic(ptr::null_mut(), || {
+        RustOp::into_raw(next(ctx, interval, Op::with_queue()))
+    })
+}
+
+/// Write the time the tick was scheduled for to `tick_ns` if the wait started
+/// by `rust_interval_next` has completed successfully, and return its status
+/// or `-1` if it's still running.
+///
+/// # Safety
+///
+/// `tick_ns` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rust_interval_next_result(op: *const RustOp, tick_ns: *mut i64) -> c_int {
+    catch_panic_status(|| op::result::<i64>(op, tick_ns))
+}
+
+/// Release the interval. Waits for its ticks that are still running are not
+/// affected and must be freed separately. Returns `0` on success or if the
+/// handle is null, and `InvalidHandle` if it has already been freed.
+#[no_mangle]
+pub extern "C" fn rust_interval_free(interval: *mut RustInterval) -> c_int {
+    catch_panic_status(|| {
+        if interval.is_null() {
+            return STATUS_OK;
+        }
+        error::status(INTERVALS.remove(interval))
+    })
+}
@@ -1,13 +1,22 @@
-use std::os::raw::c_int;
-use std::time::Duration;
-use std::thread::sleep;
-
-#[no_mangle]
-pub extern "C" fn rust_sleep(delay_ms: c_int) -> c_int {
-    let delay_ms = match delay_ms.try_into() {
-        Ok(d) => d,
-        Err(_) => return 1,
-    };
-    sleep(Duration::from_millis(delay_ms));
-    0
-}
+mod abi;
+mod backend;
+mod clock;
+mod cq;
+mod ctx;
+mod error;
+mod fork;
+mod handle;
+mod interval;
+mod lifecycle;
+mod manifest;
+mod op;
+mod panic;
+mod pool;
+mod runtime;
+mod scope;
+mod sleep;
+mod sleep_fd;
+#[cfg(feature = "testing")]
+mod testing;
+#[cfg(feature = "testing")]
+mod virtual_clock;
@@ -0,0 +1,134 @@
+use std::os::raw::c_int;
+use std::ptr;
+use std::time::Duration;
+
+use async_python_ffi_macros::blocking;
+
+use crate::abi;
+use crate::backend;
+use crate::cq;
+use crate::ctx::CTXS;
+use crate::error::{self, STATUS_OK};
+use crate::interval::INTERVALS;
+use crate::op::{self, OPS};
+use crate::panic::catch_panic_status;
+use crate::pool::{self, RustPoolConfig};
+use crate::runtime::RUNTIMES;
+use crate::scope::SCOPES;
+
+/// Configuration for the library. Null fields use the defaults.
+#[repr(C)]
+pub struct RustFfiConfig {
+    /// Must be set to `sizeof(RustFfiConfig)`.
+    pub struct_size: usize,
+    /// Configuration of the worker pool.
+    pub pool: *const RustPoolConfig,
+}
+
+/// What was left behind when the library was shut down.
+#[repr(C)]
+pub struct RustFfiReport {
+    /// Must be set to `sizeof(RustFfiReport)`.
+    pub struct_size: usize,
+    /// Operations that had not completed by the timeout and were cancelled.
+    pub cancelled: usize,
+    /// Handles of each kind that had not been freed. They remain valid and
+    /// must still be freed.
+    pub ops: usize,
+    pub scopes: usize,
+    pub intervals: usize,
+    pub contexts: usize,
+    pub runtimes: usize,
+}
+
+/// Start the library's worker pool and create its completion queue, so that
+/// failing to do so is reported here rather than by the first operation. The
+/// timer thread is started by the first sleep. Calling this is optional, since
+/// everything is also started on demand with the default configuration.
+///
+/// Returns `0` on success, `InvalidArgument` if the configuration is invalid,
+/// `AbiMismatch` if the `struct_size` of either structure is wrong, `Os` if
+/// the threads or the completion queue could not be created and
+/// `InvalidState` if the library is already running, which includes having
+/// started operations before calling this. Nothing is left running on
+/// failure.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid `RustFfiConfig` whose `pool` is
+/// null or valid for `rust_pool_init`.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ffi_init(config: *const RustFfiConfig) -> c_int {
+    catch_panic_status(|| {
+        // Only the size is read until it's known that the caller's structure
+        // has the same layout
+        if !config.is_null() {
+            let struct_size = (*config).struct_size;
+            if let Err(e) = abi::check_struct_size::<RustFfiConfig>(struct_size, "RustFfiConfig") {
+                return e.set_last();
+            }
+        }
+        let pool_config = config.as_ref().map_or(ptr::null(), |config| config.pool);
+        error::status(pool::init(pool_config).and_then(|()| {
+            // Stop the pool again so a failed call leaves nothing running and
+            // can be retried
+            cq::init().inspect_err(|_| pool::shutdown())
+        }))
+    })
+}
+
+/// Shut down the library, for example before the interpreter exits or the
+/// library is unloaded. Operations that are still running are given up to
+/// `timeout_ms` milliseconds to complete, or as long as they need if it's
+/// negative, after which the rest are cancelled. The global worker pool, the
+/// pools of runtimes that have not been freed and the timer thread are then
+/// stopped, blocking until they have exited.
+///
+/// If `report` is not null, the number of operations that were cancelled and
+/// the number of handles of each kind that have not been freed are written to
+/// it. The handles remain valid, so they can still be freed afterwards.
+/// Starting an operation after this starts the library again with the default
+/// configuration.
+///
+/// Returns `0` on success and `AbiMismatch` if the `struct_size` of `report`
+/// is wrong, in which case the library is not shut down. This must not be
+/// called from a completion callback since those run on the worker threads.
+///
+/// # Safety
+///
+/// `report` must be null or valid for reads and writes of a `RustFfiReport`.
+#[blocking]
+#[no_mangle]
+pub unsafe extern "C" fn rust_ffi_shutdown(timeout_ms: c_int, report: *mut RustFfiReport) -> c_int {
+    catch_panic_status(|| {
+        if !report.is_null() {
+            let struct_size = (*report).struct_size;
+            if let Err(e) = abi::check_struct_size::<RustFfiReport>(struct_size, "RustFfiReport") {
+                return e.set_last();
+            }
+        }
+
+        let timeout = u64::try_from(timeout_ms).ok().map(Duration::from_millis);
+        let cancelled = op::wait_until_finished(timeout);
+        // Stopping the pool drops the tasks of the operations it cancels, so
+        // none of them is still sleeping when the timer is stopped
+        pool::shutdown();
+        for runtime in RUNTIMES.values() {
+            runtime.shutdown(false);
+        }
+        backend::shutdown_timer();
+
+        if let Some(report) = report.as_mut() {
+            *report = RustFfiReport {
+                struct_size: report.struct_size,
+                cancelled,
+                ops: OPS.len(),
+                scopes: SCOPES.len(),
+                intervals: INTERVALS.len(),
+                contexts: CTXS.len(),
+                runtimes: RUNTIMES.len(),
+            };
+        }
+        STATUS_OK
+    })
+}
@@ -0,0 +1,88 @@
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::abi::ABI_VERSION;
+use crate::panic::catch_panic;
+
+/// Type of an argument or return value of an exported function.
+// Some types are only used by functions behind feature flags
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RustFfiType {
+    /// No value, only used for return types.
+    Void = 0,
+    /// `int`.
+    Int = 1,
+    /// `int64_t`.
+    Int64 = 2,
+    /// `uint64_t`.
+    Uint64 = 3,
+    /// `size_t`.
+    Size = 4,
+    /// `bool`.
+    Bool = 5,
+    /// Nul-terminated string.
+    String = 6,
+    /// Any other pointer, like an operation handle or a structure.
+    Pointer = 7,
+    /// Pointer to a function called by the library.
+    Callback = 8,
+    /// `uint32_t`.
+    Uint32 = 9,
+}
+
+/// How an exported function behaves with regards to the calling thread.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RustFfiKind {
+    /// Returns without waiting for any work to finish.
+    Immediate = 0,
+    /// Starts an operation and returns a handle to it.
+    Async = 1,
+    /// Blocks the calling thread until some work has finished.
+    Blocking = 2,
+}
+
+/// Description of an exported function.
+#[repr(C)]
+pub struct RustFfiFunction {
+    /// Name of the function as a nul-terminated string.
+    pub name: *const c_char,
+    /// Types of the function's `arg_count` arguments.
+    pub arg_types: *const RustFfiType,
+    pub arg_count: usize,
+    pub return_type: RustFfiType,
+    pub kind: RustFfiKind,
+}
+
+/// Description of the library's C ABI. The layout is part of the ABI.
+#[repr(C)]
+pub struct RustFfiManifest {
+    pub abi_version: u32,
+    /// The library's `function_count` exported functions.
+    pub functions: *const RustFfiFunction,
+    pub function_count: usize,
+}
+
+// The manifest only points to static data that is never modified
+unsafe impl Sync for RustFfiFunction {}
+unsafe impl Sync for RustFfiManifest {}
+
+// Defines `FUNCTIONS` from the exported functions found by the build script
+include!(concat!(env!("OUT_DIR"), "/manifest.rs"));
+
+static MANIFEST: RustFfiManifest = RustFfiManifest {
+    abi_version: ABI_VERSION,
+    functions: FUNCTIONS.as_ptr(),
+    function_count: FUNCTIONS.len(),
+};
+
+/// Return a description of every function exported by the library. Bindings
+/// can use it to configure themselves, or to verify that they match the
+/// library they loaded. The manifest is static and must not be freed.
+#[no_mangle]
+pub extern "C" fn rust_ffi_manifest() -> *const RustFfiManifest {
+    catch_panic(ptr::null(), || &MANIFEST)
+}
@@ -0,0 +1,554 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use async_python_ffi_macros::blocking;
+
+use crate::cq::{self, RustCompletion};
+use crate::error::{self, Error, ErrorCode, STATUS_OK, STATUS_PENDING};
+use crate::fork::ArcGuard;
+use crate::handle::Table;
+use crate::panic::{catch_panic, catch_panic_status, panic_error};
+use crate::pool::Pool;
+
+/// Function called with the `user_data` pointer given when starting the
+/// operation and the operation's status once it has completed. It comes
+/// straight from the caller, so it may be null.
+pub type RustCallback = Option<unsafe extern "C" fn(user_data: *mut c_void, status: c_int)>;
+
+struct Callback {
+    func: unsafe extern "C" fn(user_data: *mut c_void, status: c_int),
+    user_data: *mut c_void,
+}
+
+/// How the caller is told that the operation has completed, in addition to
+/// `rust_op_wait` returning.
+enum Notify {
+    Callback(Callback),
+    Queue,
+}
+
+// The user data pointer is never dereferenced by us, it's only handed back to
+// the callback. It's up to the caller to make sure that is safe to do from
+// another thread.
+unsafe impl Send for Callback {}
+
+/// Returns the error an operation completes with when it's cancelled.
+type CancelError = Box<dyn Fn() -> Error + Send>;
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    on_cancel: Option<CancelError>,
+    result: Option<Result<i64, Error>>,
+    notify: Option<Notify>,
+    waker: Option<Waker>,
+}
+
+/// Shared state of an operation running in the background. The executor and
+/// the caller's handle each own a reference to it.
+pub struct Op {
+    id: u64,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Op {
+    fn with_notify(notify: Option<Notify>) -> Arc<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            state: Mutex::new(State {
+                notify,
+                ..State::default()
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    pub fn new() -> Arc<Self> {
+        Self::with_notify(None)
+    }
+
+    /// Create an operation that calls the given callback exactly once when it
+    /// completes, including when it's cancelled.
+    pub fn with_callback(func: RustCallback, user_data: *mut c_void) -> Result<Arc<Self>, Error> {
+        let func = func.ok_or_else(|| Error::invalid_argument("callback is null"))?;
+        Ok(Self::with_notify(Some(Notify::Callback(Callback {
+            func,
+            user_data,
+        }))))
+    }
+
+    /// Create an operation that posts a record to the completion queue when it
+    /// completes, including when it's cancelled.
+    pub fn with_queue() -> Arc<Self> {
+        Self::with_notify(Some(Notify::Queue))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Complete the operation with the error returned by `f` instead of
+    /// `Cancelled` when it's cancelled or the worker pool shuts down.
+    pub fn on_cancel(&self, f: impl Fn() -> Error + Send + 'static) {
+        self.state.lock().unwrap().on_cancel = Some(Box::new(f));
+    }
+
+    fn cancelled_error(&self) -> Error {
+        cancelled_error(&self.state.lock().unwrap())
+    }
+
+    pub fn cancel(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.cancelled = true;
+            self.changed.notify_all();
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    pub fn complete(&self, result: Result<i64, Error>) {
+        let notify = {
+            let mut state = self.state.lock().unwrap();
+            if state.result.is_some() {
+                return;
+            }
+            state.result = Some(result.clone());
+            state.waker = None;
+            self.changed.notify_all();
+            state.notify.take()
+        };
+
+        // The lock must not be held while calling the callback since it may
+        // call back into the library. The error is reported on this thread so
+        // the callback can get the message using `rust_last_error_message`.
+        let value = match &result {
+            Ok(value) => *value,
+            Err(e) => e.value().unwrap_or(0),
+        };
+        let status = error::status(result);
+        match notify {
+            Some(Notify::Callback(Callback { func, user_data })) => unsafe {
+                func(user_data, status)
+            },
+            Some(Notify::Queue) => cq::push(RustCompletion {
+                op_id: self.id,
+                status,
+                result: value,
+            }),
+            None => {}
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state.lock().unwrap().result.is_some()
+    }
+
+    /// Return the result of the operation, or `None` if it's still running.
+    pub fn result(&self) -> Option<Result<i64, Error>> {
+        self.state.lock().unwrap().result.clone()
+    }
+
+    pub fn wait(&self) -> Result<i64, Error> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(result) = &state.result {
+                return result.clone();
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+
+fn cancelled_error(state: &State) -> Error {
+    state
+        .on_cancel
+        .as_ref()
+        .map_or_else(Error::cancelled, |f| f())
+}
+
+/// Values that can be returned by an operation. They are stored as an `i64`
+/// so they fit in a completion record.
+pub trait OpValue {
+    fn into_raw(self) -> i64;
+    fn from_raw(raw: i64) -> Self;
+}
+
+impl OpValue for () {
+    fn into_raw(self) -> i64 {
+        0
+    }
+
+    fn from_raw(_: i64) -> Self {}
+}
+
+impl OpValue for bool {
+    fn into_raw(self) -> i64 {
+        self as i64
+    }
+
+    fn from_raw(raw: i64) -> Self {
+        raw != 0
+    }
+}
+
+macro_rules! impl_op_value {
+    ($($ty:ty),*) => {
+        $(
+            impl OpValue for $ty {
+                fn into_raw(self) -> i64 {
+                    self as i64
+                }
+
+                fn from_raw(raw: i64) -> Self {
+                    raw as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_op_value!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+/// Future that resolves to the wrapped future's output, unless the operation
+/// is cancelled first. A panic while polling the wrapped future fails the
+/// operation rather than taking down the executor.
+struct Cancellable<F> {
+    op: Arc<Op>,
+    future: Pin<Box<F>>,
+    unpolled: Option<Unpolled>,
+}
+
+impl<F, T> Future for Cancellable<F>
+where
+    F: Future<Output = Result<T, Error>>,
+    T: OpValue,
+{
+    type Output = Result<i64, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let poll = self.poll_future(cx);
+        self.unpolled = None;
+        poll
+    }
+}
+
+impl<F, T> Cancellable<F>
+where
+    F: Future<Output = Result<T, Error>>,
+    T: OpValue,
+{
+    fn poll_future(&mut self, cx: &mut Context) -> Poll<Result<i64, Error>> {
+        // The waker is registered before checking the flag so a cancellation
+        // can never slip in between the two
+        {
+            let mut state = self.op.state.lock().unwrap();
+            if state.cancelled {
+                return Poll::Ready(Err(cancelled_error(&state)));
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+        let future = self.future.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(poll) => poll.map(|result| result.map(OpValue::into_raw)),
+            Err(payload) => Poll::Ready(Err(panic_error(payload))),
+        }
+    }
+}
+
+/// Number of operations in some state, which threads can wait to drop to zero.
+struct Counter {
+    count: AtomicUsize,
+    /// Number of threads in `wait_for_zero`, so the lock is only taken when
+    /// there's someone to notify.
+    waiting: AtomicUsize,
+    lock: Mutex<()>,
+    zero: Condvar,
+}
+
+impl Counter {
+    const fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            waiting: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            zero: Condvar::new(),
+        }
+    }
+
+    fn increment(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn decrement(&self) {
+        // The lock makes sure the notification can't slip in between a waiter
+        // checking the count and starting to wait
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 && self.waiting.load(Ordering::SeqCst) > 0
+        {
+            let _lock = self.lock.lock().unwrap();
+            self.zero.notify_all();
+        }
+    }
+
+    /// Block until the count is zero or the timeout has passed, and return
+    /// the count.
+    fn wait_for_zero(&self, timeout: Option<Duration>) -> usize {
+        // A timeout too long to be represented is as good as none
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        let mut lock = self.lock.lock().unwrap();
+        while self.count.load(Ordering::SeqCst) > 0 {
+            lock = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    self.zero.wait_timeout(lock, deadline - now).unwrap().0
+                }
+                None => self.zero.wait(lock).unwrap(),
+            };
+        }
+        drop(lock);
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+/// Operations that have been started, but whose task has not yet been polled.
+static UNPOLLED: Counter = Counter::new();
+/// Operations whose task has not yet finished.
+static RUNNING: Counter = Counter::new();
+
+/// Counts an operation as unpolled for as long as it's alive.
+struct Unpolled;
+
+impl Unpolled {
+    fn new() -> Self {
+        UNPOLLED.increment();
+        Self
+    }
+}
+
+impl Drop for Unpolled {
+    fn drop(&mut self) {
+        UNPOLLED.decrement();
+    }
+}
+
+/// Block until every operation that has been started has been polled at least
+/// once, so whatever they do right away, like starting a sleep, has happened,
+/// or the timeout has passed. Returns the number of operations that have still
+/// not been polled.
+#[cfg(feature = "testing")]
+pub fn wait_until_polled(timeout: Duration) -> usize {
+    UNPOLLED.wait_for_zero(Some(timeout))
+}
+
+/// Block until every operation that has been started has finished, or the
+/// timeout has passed. Returns the number of operations that are still
+/// running.
+pub fn wait_until_finished(timeout: Option<Duration>) -> usize {
+    RUNNING.wait_for_zero(timeout)
+}
+
+/// Locks held on the operations and the counters across `fork`.
+pub struct ForkGuard {
+    ops: Vec<ArcGuard<Op, State>>,
+    _counters: [MutexGuard<'static, ()>; 2],
+}
+
+/// Lock the given operations, skipping duplicates, and the counters.
+pub fn prepare_fork(ops: impl Iterator<Item = Arc<Op>>) -> ForkGuard {
+    let mut seen = HashSet::new();
+    let ops = ops
+        .filter(|op| seen.insert(Arc::as_ptr(op)))
+        .map(|op| ArcGuard::lock(op, |op| &op.state))
+        .collect();
+    ForkGuard {
+        ops,
+        _counters: [UNPOLLED.lock.lock().unwrap(), RUNNING.lock.lock().unwrap()],
+    }
+}
+
+impl ForkGuard {
+    /// Complete the operations in the child, where their tasks don't exist
+    /// since only the thread that forked is copied, and forget the counts and
+    /// waiters of the parent. Nothing is notified, since the callbacks and the
+    /// completion queue belong to the parent.
+    pub fn reset(mut self) {
+        for state in &mut self.ops {
+            if state.result.is_none() {
+                state.result = Some(Err(Error::new(
+                    ErrorCode::Cancelled,
+                    "operation was cancelled because the process forked",
+                )));
+            }
+            state.notify = None;
+            // Dropping the waker could drop the task, which must not run
+            mem::forget(state.waker.take());
+        }
+        for counter in [&UNPOLLED, &RUNNING] {
+            counter.count.store(0, Ordering::SeqCst);
+            counter.waiting.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Completes the operation as cancelled if its task is dropped before it has
+/// completed, which happens when the executor is shut down. The operation is
+/// counted as running until then.
+struct CompleteOnDrop(Arc<Op>);
+
+impl CompleteOnDrop {
+    fn new(op: Arc<Op>) -> Self {
+        RUNNING.increment();
+        Self(op)
+    }
+}
+
+impl Drop for CompleteOnDrop {
+    fn drop(&mut self) {
+        self.0.complete(Err(self.0.cancelled_error()));
+        RUNNING.decrement();
+    }
+}
+
+/// Run the given future on the given worker pool and complete the operation
+/// with its output once done.
+pub fn spawn<F, T>(pool: &Pool, op: Arc<Op>, future: F) -> Arc<Op>
+where
+    F: Future<Output = Result<T, Error>> + Send + 'static,
+    T: OpValue,
+{
+    // The guard is created outside of the task so the operation is completed
+    // even if the task is dropped before it's polled for the first time
+    let guard = CompleteOnDrop::new(op.clone());
+    let unpolled = Unpolled::new();
+    pool.spawn(op.clone(), async move {
+        let result = Cancellable {
+            op: guard.0.clone(),
+            future: Box::pin(future),
+            unpolled: Some(unpolled),
+        }
+        .await;
+        guard.0.complete(result);
+    });
+    op
+}
+
+/// Opaque handle given to the caller. It must be released using `rust_op_free`.
+pub struct RustOp {
+    _private: [u8; 0],
+}
+
+pub static OPS: Table<RustOp, Arc<Op>> = Table::new("operation");
+
+impl RustOp {
+    pub fn into_raw(op: Arc<Op>) -> *mut RustOp {
+        OPS.insert(op)
+    }
+
+    /// Return the operation the handle refers to.
+    pub fn get(op: *const RustOp) -> Result<Arc<Op>, Error> {
+        OPS.get(op)
+    }
+}
+
+/// Return the unique id of the given operation, which is used to identify it
+/// in completion records. Returns `0` if the handle is null or has been freed,
+/// in which case the error is reported on the calling thread.
+#[no_mangle]
+pub extern "C" fn rust_op_id(op: *const RustOp) -> u64 {
+    catch_panic(0, || match RustOp::get(op) {
+        Ok(op) => op.id(),
+        Err(e) => {
+            e.set_last();
+            0
+        }
+    })
+}
+
+/// Return the status of the given operation without blocking, or `-1` if it
+/// has not yet completed. If the operation failed its error is reported on the
+/// calling thread.
+#[no_mangle]
+pub extern "C" fn rust_op_poll(op: *const RustOp) -> c_int {
+    catch_panic_status(|| match RustOp::get(op) {
+        Ok(op) => op.result().map_or(STATUS_PENDING, error::status),
+        Err(e) => e.set_last(),
+    })
+}
+
+/// Write the result of the given operation to `out` if it has completed
+/// successfully, or the value reported along with its error if there is one.
+/// Returns the same status as `rust_op_poll`.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writing a `T`.
+pub unsafe fn result<T: OpValue>(op: *const RustOp, out: *mut T) -> c_int {
+    let op = match RustOp::get(op) {
+        Ok(op) => op,
+        Err(e) => return e.set_last(),
+    };
+    match op.result() {
+        Some(Ok(value)) => {
+            if !out.is_null() {
+                out.write(T::from_raw(value));
+            }
+            error::status(Ok(()))
+        }
+        Some(Err(e)) => {
+            if let (Some(value), false) = (e.value(), out.is_null()) {
+                out.write(T::from_raw(value));
+            }
+            e.set_last()
+        }
+        None => STATUS_PENDING,
+    }
+}
+
+/// Request cancellation of the given operation. This returns immediately and
+/// the operation completes with the `Cancelled` status as soon as the executor
+/// notices. Returns `0` on success, `InvalidArgument` if the handle is null
+/// and `InvalidHandle` if it has been freed.
+#[no_mangle]
+pub extern "C" fn rust_op_cancel(op: *const RustOp) -> c_int {
+    catch_panic_status(|| error::status(RustOp::get(op).map(|op| op.cancel())))
+}
+
+/// Block until the given operation has completed and return its status. If
+/// the operation failed its error is reported on the calling thread. Freeing
+/// the handle from another thread while waiting doesn't affect the wait.
+#[blocking]
+#[no_mangle]
+pub extern "C" fn rust_op_wait(op: *const RustOp) -> c_int {
+    catch_panic_status(|| error::status(RustOp::get(op).and_then(|op| op.wait())))
+}
+
+/// Release the given handle. An operation that is still running is cancelled
+/// since there is no longer any way to observe its result. Returns `0` on
+/// success or if the handle is null, and `InvalidHandle` if it has already
+/// been freed.
+#[no_mangle]
+pub extern "C" fn rust_op_free(op: *mut RustOp) -> c_int {
+    catch_panic_status(|| {
+        if op.is_null() {
+            return STATUS_OK;
+        }
+        error::status(OPS.remove(op).map(|op| op.cancel()))
+    })
+}
@@ -0,0 +1,45 @@
+use std::any::Any;
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::error::{Error, ErrorCode};
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Convert a caught panic into an error. With the `abort-on-panic` feature the
+/// process is aborted instead.
+pub fn panic_error(payload: Box<dyn Any + Send>) -> Error {
+    let message = panic_message(&*payload);
+    if cfg!(feature = "abort-on-panic") {
+        eprintln!("async-python-ffi: aborting due to panic: {}", message);
+        std::process::abort();
+    }
+    Error::new(ErrorCode::Panic, format!("panic: {}", message))
+}
+
+/// Run `f` and return its result. If it panics the panic is reported as the
+/// calling thread's last error and `on_panic` is returned instead, since
+/// unwinding across the C ABI is undefined behaviour. Every exported function
+/// must be wrapped in this.
+pub fn catch_panic<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            panic_error(payload).set_last();
+            on_panic
+        }
+    }
+}
+
+/// Same as `catch_panic` for functions that return a status code.
+pub fn catch_panic_status(f: impl FnOnce() -> c_int) -> c_int {
+    catch_panic(ErrorCode::Panic as c_int, f)
+}
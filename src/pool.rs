@@ -0,0 +1,277 @@
+use std::ffi::CStr;
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use async_python_ffi_macros::blocking;
+
+use crate::abi;
+use crate::backend::Executor;
+use crate::error::{self, Error};
+use crate::fork::{self, ArcGuard};
+use crate::op::Op;
+use crate::panic::{catch_panic, catch_panic_status};
+
+const DEFAULT_THREAD_NAME: &str = "rust-ffi-worker";
+
+/// Configuration for the worker pool. Zero or null fields use the defaults.
+#[repr(C)]
+pub struct RustPoolConfig {
+    /// Must be set to `sizeof(RustPoolConfig)`.
+    pub struct_size: usize,
+    /// Number of worker threads. Defaults to the available parallelism.
+    pub threads: usize,
+    /// Prefix for the worker thread names, which are suffixed with the
+    /// worker's index. Defaults to `rust-ffi-worker`.
+    pub thread_name: *const c_char,
+    /// Stack size of the worker threads in bytes. Defaults to Rust's default.
+    pub stack_size: usize,
+}
+
+/// Settings that the workers of a pool are started with.
+#[derive(Clone)]
+struct Settings {
+    threads: usize,
+    thread_name: String,
+    stack_size: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            thread_name: DEFAULT_THREAD_NAME.into(),
+            stack_size: 0,
+        }
+    }
+}
+
+impl Settings {
+    /// Read the settings from the caller's configuration, or use the defaults
+    /// if `config` is null.
+    ///
+    /// # Safety
+    ///
+    /// `config` must be null or point to a valid `RustPoolConfig` whose
+    /// `thread_name` is null or a valid nul-terminated string.
+    unsafe fn from_config(config: *const RustPoolConfig) -> Result<Self, Error> {
+        // Only the size is read until it's known that the caller's structure
+        // has the same layout
+        if !config.is_null() {
+            abi::check_struct_size::<RustPoolConfig>((*config).struct_size, "RustPoolConfig")?;
+        }
+
+        let config = match config.as_ref() {
+            Some(config) => config,
+            None => return Ok(Self::default()),
+        };
+        let defaults = Self::default();
+        let threads = match config.threads {
+            0 => defaults.threads,
+            n => n,
+        };
+        let thread_name = if config.thread_name.is_null() {
+            defaults.thread_name
+        } else {
+            CStr::from_ptr(config.thread_name)
+                .to_str()
+                .map_err(|_| Error::invalid_argument("thread name is not valid UTF-8"))?
+                .into()
+        };
+        Ok(Self {
+            threads,
+            thread_name,
+            stack_size: config.stack_size,
+        })
+    }
+
+    fn start(&self) -> io::Result<Executor> {
+        Executor::new(self.threads, &self.thread_name, self.stack_size)
+    }
+}
+
+struct State {
+    executor: Option<Executor>,
+    /// What the workers are started with, or `None` for the defaults.
+    settings: Option<Settings>,
+    /// Set once the pool has been freed, after which operations fail instead
+    /// of starting it again.
+    freed: bool,
+}
+
+/// Worker pool that operations run on. Its workers are started on demand by
+/// the first operation, and again by the first operation after it has been
+/// shut down.
+pub struct Pool {
+    state: Mutex<State>,
+}
+
+/// Pool used by operations that aren't started with a runtime of their own.
+static POOL: Pool = Pool {
+    state: Mutex::new(State {
+        executor: None,
+        settings: None,
+        freed: false,
+    }),
+};
+
+/// Return the pool used by operations that aren't started with a runtime.
+pub fn global() -> &'static Pool {
+    &POOL
+}
+
+impl Pool {
+    /// Create a pool and start its workers with the given configuration, or
+    /// the default configuration if `config` is null.
+    ///
+    /// # Safety
+    ///
+    /// `config` must be null or point to a valid `RustPoolConfig` whose
+    /// `thread_name` is null or a valid nul-terminated string.
+    pub unsafe fn new(config: *const RustPoolConfig) -> Result<Self, Error> {
+        let settings = Settings::from_config(config)?;
+        fork::register();
+        let executor = settings.start().map_err(Error::os)?;
+        Ok(Self {
+            state: Mutex::new(State {
+                executor: Some(executor),
+                settings: Some(settings),
+                freed: false,
+            }),
+        })
+    }
+
+    /// Run the task for the given operation on the pool, starting the workers
+    /// if they're not running.
+    pub fn spawn<F>(&self, op: Arc<Op>, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut state = self.state.lock().unwrap();
+        if state.executor.is_none() {
+            let started = if state.freed {
+                Err(Error::invalid_state("runtime has been freed"))
+            } else {
+                fork::register();
+                let settings = state.settings.clone().unwrap_or_default();
+                settings.start().map_err(Error::os)
+            };
+            match started {
+                Ok(executor) => state.executor = Some(executor),
+                Err(e) => {
+                    // Without any workers there is nothing we can do but to
+                    // fail the operation right away. The lock must be released
+                    // first since completing it may call back into the library.
+                    drop(state);
+                    op.complete(Err(e));
+                    return;
+                }
+            }
+        }
+        state.executor.as_ref().unwrap().spawn(op, task);
+    }
+
+    /// Cancel all operations that have not yet completed and stop the
+    /// workers, blocking until they have exited. The next operation starts
+    /// them again, unless `free` is set in which case it fails instead.
+    pub fn shutdown(&self, free: bool) {
+        let executor = {
+            let mut state = self.state.lock().unwrap();
+            state.freed |= free;
+            state.executor.take()
+        };
+        if let Some(executor) = executor {
+            executor.shutdown();
+        }
+    }
+}
+
+/// Start the global worker pool with the given configuration, or the default
+/// configuration if `config` is null.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid `RustPoolConfig` whose
+/// `thread_name` is null or a valid nul-terminated string.
+pub unsafe fn init(config: *const RustPoolConfig) -> Result<(), Error> {
+    let settings = Settings::from_config(config)?;
+    let mut state = POOL.state.lock().unwrap();
+    if state.executor.is_some() {
+        return Err(Error::invalid_state("worker pool is already running"));
+    }
+    fork::register();
+    state.executor = Some(settings.start().map_err(Error::os)?);
+    state.settings = Some(settings);
+    Ok(())
+}
+
+/// Cancel all operations on the global worker pool that have not yet
+/// completed and stop it, blocking until all workers have exited. The next
+/// operation starts it again with the default configuration.
+pub fn shutdown() {
+    POOL.state.lock().unwrap().settings = None;
+    POOL.shutdown(false);
+}
+
+/// Locks held across `fork` on the global pool and the pools of the given
+/// runtimes, so none of them is being started or stopped while the process
+/// is copied.
+pub struct ForkGuard {
+    global: MutexGuard<'static, State>,
+    runtimes: Vec<ArcGuard<Pool, State>>,
+}
+
+pub fn prepare_fork<'a>(runtimes: impl Iterator<Item = &'a Arc<Pool>>) -> ForkGuard {
+    ForkGuard {
+        global: POOL.state.lock().unwrap(),
+        runtimes: runtimes
+            .map(|pool| ArcGuard::lock(pool.clone(), |pool| &pool.state))
+            .collect(),
+    }
+}
+
+impl ForkGuard {
+    /// Forget the pools in the child, whose workers only exist in the parent.
+    /// They can't be shut down since there are no threads to join, and
+    /// dropping the tasks could run code that relies on them. The next
+    /// operation on each pool starts new workers with the same settings.
+    pub fn reset(mut self) {
+        mem::forget(self.global.executor.take());
+        for state in &mut self.runtimes {
+            mem::forget(state.executor.take());
+        }
+    }
+}
+
+/// Start the worker pool with the given configuration, or the default
+/// configuration if `config` is null. Operations started before calling this
+/// start the pool with the default configuration, so this must be called
+/// before starting any operations to have any effect.
+///
+/// Returns `0` on success, `InvalidArgument` if the configuration is invalid,
+/// `AbiMismatch` if its `struct_size` is wrong, `Os` if the threads could not
+/// be spawned and `InvalidState` if the pool is already running.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid `RustPoolConfig` whose
+/// `thread_name` is null or a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rust_pool_init(config: *const RustPoolConfig) -> c_int {
+    catch_panic_status(|| error::status(init(config)))
+}
+
+/// Cancel all operations that have not yet completed and stop the worker pool.
+/// This blocks until all workers have exited. Starting a new operation after
+/// this starts the pool again with the default configuration.
+///
+/// This must not be called from a completion callback since those run on the
+/// worker threads.
+#[blocking]
+#[no_mangle]
+pub extern "C" fn rust_pool_shutdown() {
+    catch_panic((), shutdown)
+}
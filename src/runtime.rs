@@ -0,0 +1,69 @@
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::Arc;
+
+use async_python_ffi_macros::blocking;
+
+use crate::error::{self, Error, STATUS_OK};
+use crate::handle::Table;
+use crate::panic::{catch_panic, catch_panic_status};
+use crate::pool::{Pool, RustPoolConfig};
+
+/// Opaque handle to a runtime. It must be released using `rust_runtime_free`.
+pub struct RustRuntime {
+    _private: [u8; 0],
+}
+
+pub static RUNTIMES: Table<RustRuntime, Arc<Pool>> = Table::new("runtime");
+
+impl RustRuntime {
+    /// Return the worker pool of the runtime the handle refers to.
+    pub fn get(runtime: *const RustRuntime) -> Result<Arc<Pool>, Error> {
+        RUNTIMES.get(runtime)
+    }
+}
+
+/// Create a runtime with a worker pool of its own, configured the same way as
+/// the global pool by `rust_pool_init`. Operations started with a context
+/// created for the runtime run on its pool instead of the global one, so
+/// separate components can each own a runtime and shut it down without
+/// affecting the others. The timer thread and the completion queue are shared
+/// by all runtimes.
+///
+/// Returns null on failure, in which case the error is reported on the
+/// calling thread.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid `RustPoolConfig` whose
+/// `thread_name` is null or a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rust_runtime_new(config: *const RustPoolConfig) -> *mut RustRuntime {
+    catch_panic(ptr::null_mut(), || match Pool::new(config) {
+        Ok(pool) => RUNTIMES.insert(Arc::new(pool)),
+        Err(e) => {
+            e.set_last();
+            ptr::null_mut()
+        }
+    })
+}
+
+/// Release the runtime, cancelling the operations that are still running on it
+/// and stopping its worker pool. This blocks until all of its workers have
+/// exited, except when called from the completion callback of an operation
+/// running on the runtime, where the workers exit once the callback returns.
+/// Operations started afterwards with a context created for the runtime fail
+/// with the `InvalidState` status.
+///
+/// Returns `0` on success or if the handle is null, and `InvalidHandle` if it
+/// has already been freed.
+#[blocking]
+#[no_mangle]
+pub extern "C" fn rust_runtime_free(runtime: *mut RustRuntime) -> c_int {
+    catch_panic_status(|| {
+        if runtime.is_null() {
+            return STATUS_OK;
+        }
+        error::status(RUNTIMES.remove(runtime).map(|pool| pool.shutdown(true)))
+    })
+}
@@ -0,0 +1,195 @@
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::error::{self, Error, STATUS_OK};
+use crate::fork::ArcGuard;
+use crate::handle::Table;
+use crate::op::{Op, RustOp};
+use crate::panic::{catch_panic, catch_panic_status};
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    /// Operations and nested scopes that are cancelled along with the scope.
+    /// They are weak so the scope doesn't keep them alive after they have
+    /// been freed.
+    ops: Vec<Weak<Op>>,
+    /// Number of operations left after they were last pruned.
+    pruned_len: usize,
+    children: Vec<Weak<Scope>>,
+}
+
+/// Group of operations that are cancelled together, like an asyncio
+/// `TaskGroup`.
+#[derive(Default)]
+pub struct Scope {
+    state: Mutex<State>,
+}
+
+impl Scope {
+    fn new(parent: Option<&Scope>) -> Arc<Self> {
+        let scope = Arc::new(Self::default());
+        if let Some(parent) = parent {
+            let mut state = parent.state.lock().unwrap();
+            if state.cancelled {
+                scope.state.lock().unwrap().cancelled = true;
+            } else {
+                state.children.retain(|child| child.strong_count() > 0);
+                state.children.push(Arc::downgrade(&scope));
+            }
+        }
+        scope
+    }
+
+    pub fn add(&self, op: &Arc<Op>) {
+        let mut state = self.state.lock().unwrap();
+        if state.cancelled {
+            drop(state);
+            op.cancel();
+            return;
+        }
+        // Operations that have completed or been freed are dropped once the
+        // list has doubled since they were last, so long-lived scopes don't
+        // grow without bound and adding stays cheap on average
+        if state.ops.len() >= 2 * state.pruned_len {
+            state
+                .ops
+                .retain(|op| op.upgrade().is_some_and(|op| !op.is_complete()));
+            state.pruned_len = state.ops.len();
+        }
+        state.ops.push(Arc::downgrade(op));
+    }
+
+    fn cancel(&self) {
+        let (ops, children) = {
+            let mut state = self.state.lock().unwrap();
+            state.cancelled = true;
+            (
+                std::mem::take(&mut state.ops),
+                std::mem::take(&mut state.children),
+            )
+        };
+        // Cancelling may call back into the library, so the lock must not be
+        // held while doing it
+        for op in ops.iter().filter_map(Weak::upgrade) {
+            op.cancel();
+        }
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+/// Locks held on the scopes across `fork`.
+pub struct ForkGuard(Vec<ArcGuard<Scope, State>>);
+
+pub fn prepare_fork<'a>(scopes: impl Iterator<Item = &'a Arc<Scope>>) -> ForkGuard {
+    ForkGuard(
+        scopes
+            .map(|scope| ArcGuard::lock(scope.clone(), |scope| &scope.state))
+            .collect(),
+    )
+}
+
+impl ForkGuard {
+    /// Return the operations in the scopes that are still alive.
+    pub fn ops(&self) -> Vec<Arc<Op>> {
+        self.0
+            .iter()
+            .flat_map(|state| state.ops.iter().filter_map(Weak::upgrade))
+            .collect()
+    }
+}
+
+/// Opaque handle to a cancellation scope. It must be released using
+/// `rust_scope_free`.
+pub struct RustScope {
+    _private: [u8; 0],
+}
+
+pub static SCOPES: Table<RustScope, Arc<Scope>> = Table::new("scope");
+
+impl RustScope {
+    /// Return the scope the handle refers to.
+    pub fn get(scope: *const RustScope) -> Result<Arc<Scope>, Error> {
+        SCOPES.get(scope)
+    }
+}
+
+/// Create a cancellation scope. Operations added to it using `rust_scope_add`
+/// are all cancelled when the scope is cancelled. If `parent` is not null the
+/// scope is nested in it, so cancelling the parent cancels this scope too,
+/// while cancelling this scope leaves the parent alone.
+///
+/// Returns null on failure, in which case the error is reported on the
+/// calling thread.
+#[no_mangle]
+pub extern "C" fn rust_scope_new(parent: *const RustScope) -> *mut RustScope {
+    catch_panic(ptr::null_mut(), || {
+        let parent = if parent.is_null() {
+            None
+        } else {
+            match RustScope::get(parent) {
+                Ok(parent) => Some(parent),
+                Err(e) => {
+                    e.set_last();
+                    return ptr::null_mut();
+                }
+            }
+        };
+        SCOPES.insert(Scope::new(parent.as_deref()))
+    })
+}
+
+/// Add an operation to the scope, so it's cancelled when the scope is. An
+/// operation added to a scope that has already been cancelled is cancelled
+/// right away. Returns `0` on success, `InvalidArgument` if either handle is
+/// null and `InvalidHandle` if either has been freed.
+#[no_mangle]
+pub extern "C" fn rust_scope_add(scope: *const RustScope, op: *const RustOp) -> c_int {
+    catch_panic_status(|| match (RustScope::get(scope), RustOp::get(op)) {
+        (Ok(scope), Ok(op)) => {
+            scope.add(&op);
+            STATUS_OK
+        }
+        (Err(e), _) | (_, Err(e)) => e.set_last(),
+    })
+}
+
+/// Cancel every operation in the scope and every scope nested in it. This
+/// returns immediately, and the operations complete with the `Cancelled`
+/// status as soon as the executor notices. Returns `0` on success,
+/// `InvalidArgument` if the handle is null and `InvalidHandle` if it has been
+/// freed.
+#[no_mangle]
+pub extern "C" fn rust_scope_cancel(scope: *const RustScope) -> c_int {
+    catch_panic_status(|| error::status(RustScope::get(scope).map(|scope| scope.cancel())))
+}
+
+/// Return whether the scope has been cancelled, either directly or through
+/// one of the scopes it's nested in. Returns `false` if the handle is null or
+/// has been freed, in which case the error is reported on the calling thread.
+#[no_mangle]
+pub extern "C" fn rust_scope_is_cancelled(scope: *const RustScope) -> bool {
+    catch_panic(false, || match RustScope::get(scope) {
+        Ok(scope) => scope.state.lock().unwrap().cancelled,
+        Err(e) => {
+            e.set_last();
+            false
+        }
+    })
+}
+
+/// Release the scope, cancelling everything that is still running in it since
+/// there is no longer any way to cancel it. Returns `0` on success or if the
+/// handle is null, and `InvalidHandle` if it has already been freed.
+#[no_mangle]
+pub extern "C" fn rust_scope_free(scope: *mut RustScope) -> c_int {
+    catch_panic_status(|| {
+        if scope.is_null() {
+            return STATUS_OK;
+        }
+        error::status(SCOPES.remove(scope).map(|scope| scope.cancel()))
+    })
+}
@@ -0,0 +1,255 @@
+use std::hint;
+use std::io;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_python_ffi_macros::{async_ffi, blocking};
+
+use crate::clock::{self, RustClock};
+use crate::ctx::{self, RustCtx};
+use crate::error::{self, Error};
+use crate::op::{self, Op, RustCallback, RustOp};
+use crate::panic::{catch_panic, catch_panic_status};
+#[cfg(feature = "testing")]
+use crate::virtual_clock;
+
+/// Longest time an async sleep on a clock other than the monotonic one waits
+/// before checking the clock again. The timers only follow the monotonic clock,
+/// so this bounds how late the sleep completes if the other clock jumps ahead
+/// or the system is suspended.
+const CLOCK_RECHECK: Duration = Duration::from_secs(1);
+
+/// Slack used by `rust_sleep_precise` when none is given. The OS usually wakes
+/// up sleeping threads well within this, so the tail is rarely spun for long.
+const DEFAULT_SLACK: Duration = Duration::from_millis(1);
+
+/// Precise sleeps yield the thread until this close to the deadline, and then
+/// spin for the rest of it since yielding may take longer than that.
+const SPIN_TAIL: Duration = Duration::from_micros(50);
+
+pub fn delay_from_ms(delay_ms: c_int) -> Result<Duration, Error> {
+    match delay_ms.try_into() {
+        Ok(d) => Ok(Duration::from_millis(d)),
+        Err(_) => Err(Error::invalid_argument(format!(
+            "delay must not be negative, got {}",
+            delay_ms,
+        ))),
+    }
+}
+
+/// Block the calling thread for `delay_ms` milliseconds. Returns `0` on
+/// success and `InvalidArgument` if the delay is negative.
+#[blocking]
+#[no_mangle]
+pub extern "C" fn rust_sleep(delay_ms: c_int) -> c_int {
+    catch_panic_status(|| error::status(delay_from_ms(delay_ms).map(clock::block_for)))
+}
+
+#[async_ffi(name = "rust_sleep")]
+async fn sleep(delay_ms: c_int) -> Result<(), Error> {
+    clock::sleep(delay_from_ms(delay_ms)?).await?;
+    Ok(())
+}
+
+/// Start a sleep that calls `callback` with `user_data` and the status from a
+/// worker thread once it has finished.
+///
+/// The returned handle can be used to cancel the sleep. It must be freed using
+/// `rust_op_free`, which cancels the sleep if it's still running. Returns null
+/// with `InvalidArgument` if `callback` is null.
+#[no_mangle]
+pub extern "C" fn rust_sleep_callback(
+    ctx: *const RustCtx,
+    delay_ms: c_int,
+    callback: RustCallback,
+    user_data: *mut c_void,
+) -> *mut RustOp {
+    catch_panic(ptr::null_mut(), || {
+        match Op::with_callback(callback, user_data) {
+            Ok(op) => RustOp::into_raw(__async_ffi_rust_sleep(ctx, op, delay_ms)),
+            Err(e) => {
+                e.set_last();
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Start a sleep that posts a record to the completion queue once it has
+/// finished. Use `rust_op_id` to match the handle with its completion record.
+///
+/// The returned handle can be used to cancel the sleep. It must be freed using
+/// `rust_op_free`, which cancels the sleep if it's still running.
+#[no_mangle]
+pub extern "C" fn rust_sleep_submit(ctx: *const RustCtx, delay_ms: c_int) -> *mut RustOp {
+    catch_panic(ptr::null_mut(), || {
+        RustOp::into_raw(__async_ffi_rust_sleep(ctx, Op::with_queue(), delay_ms))
+    })
+}
+
+fn block_until(deadline_ns: i64, clock: c_int) -> Result<(), Error> {
+    let clock = RustClock::from_raw(clock)?;
+    #[cfg(feature = "testing")]
+    if virtual_clock::block_until(clock, deadline_ns) {
+        return Ok(());
+    }
+
+    // The clocks don't go below zero, so earlier deadlines have already passed
+    // and the kernel rejects them
+    let deadline = clock::timespec(deadline_ns.max(0));
+    loop {
+        let errno = unsafe {
+            libc::clock_nanosleep(clock.id(), libc::TIMER_ABSTIME, &deadline, ptr::null_mut())
+        };
+        match errno {
+            0 => return Ok(()),
+            // The deadline is absolute, so the sleep can simply be restarted
+            // if it was interrupted by a signal
+            libc::EINTR => continue,
+            errno => return Err(Error::os(io::Error::from_raw_os_error(errno))),
+        }
+    }
+}
+
+fn duration_from_ns(value_ns: i64, name: &str) -> Result<Duration, Error> {
+    match value_ns.try_into() {
+        Ok(ns) => Ok(Duration::from_nanos(ns)),
+        Err(_) => Err(Error::invalid_argument(format!(
+            "{} must not be negative, got {}",
+            name, value_ns,
+        ))),
+    }
+}
+
+fn sleep_precise(delay_ns: i64, slack_ns: i64) -> Result<(), Error> {
+    let delay = duration_from_ns(delay_ns, "delay")?;
+    let slack = match duration_from_ns(slack_ns, "slack")? {
+        Duration::ZERO => DEFAULT_SLACK,
+        slack => slack,
+    };
+    // The virtual clock is exact, so there's nothing to spin for
+    #[cfg(feature = "testing")]
+    if virtual_clock::block_for(delay) {
+        return Ok(());
+    }
+
+    let deadline = Instant::now()
+        .checked_add(delay)
+        .ok_or_else(|| Error::invalid_argument(format!("delay of {}ns is too long", delay_ns)))?;
+
+    if let Some(coarse) = deadline.checked_sub(slack) {
+        thread::sleep(coarse.saturating_duration_since(Instant::now()));
+    }
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        } else if remaining > SPIN_TAIL {
+            thread::yield_now();
+        } else {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Block the calling thread for `delay_ns` nanoseconds, sleeping until
+/// `slack_ns` nanoseconds before the deadline and busy-waiting for the rest.
+/// This is far more accurate than `rust_sleep`, which is at the mercy of how
+/// quickly the OS wakes the thread up, at the cost of keeping a CPU busy for
+/// up to the slack. A slack of zero uses a default of one millisecond.
+///
+/// Returns `0` on success and `InvalidArgument` if the delay or slack is
+/// negative.
+#[blocking]
+#[no_mangle]
+pub extern "C" fn rust_sleep_precise(delay_ns: i64, slack_ns: i64) -> c_int {
+    catch_panic_status(|| error::status(sleep_precise(delay_ns, slack_ns)))
+}
+
+/// Block the calling thread until `clock` reaches `deadline_ns` nanoseconds,
+/// where `clock` is one of `RustClock`. Deadlines that have already passed
+/// return right away. Since the deadline is absolute, repeated sleeps don't
+/// accumulate any drift.
+///
+/// Returns `0` on success and `InvalidArgument` if the clock is unknown.
+#[blocking]
+#[no_mangle]
+pub extern "C" fn rust_sleep_until(deadline_ns: i64, clock: c_int) -> c_int {
+    catch_panic_status(|| error::status(block_until(deadline_ns, clock)))
+}
+
+#[async_ffi(name = "rust_sleep_until")]
+async fn sleep_until(deadline_ns: i64, clock: c_int) -> Result<(), Error> {
+    wait_until(RustClock::from_raw(clock)?, deadline_ns).await
+}
+
+/// Sleep until `clock` reaches `deadline_ns` without occupying a worker
+/// thread.
+pub async fn wait_until(clock: RustClock, deadline_ns: i64) -> Result<(), Error> {
+    loop {
+        let remaining = clock.until(deadline_ns)?;
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        match clock {
+            RustClock::Monotonic => clock::sleep(remaining).await?,
+            _ => clock::sleep(remaining.min(CLOCK_RECHECK)).await?,
+        }
+    }
+}
+
+/// Start a sleep of `delay_ns` nanoseconds that can be woken up early, like
+/// `nanosleep` being interrupted by a signal. Cancelling the sleep using
+/// `rust_op_cancel` or shutting down the worker pool wakes it up, and it then
+/// completes with the `Interrupted` status instead of `Cancelled`. Use
+/// `rust_sleep_interruptible_result` to find out how much of the sleep was
+/// left, for example to resume it later.
+///
+/// The sleep is subject to the deadline and scope of `ctx`, but reaching the
+/// deadline fails it with `Timeout` rather than interrupting it. The returned
+/// handle must be freed using `rust_op_free`, which cancels the sleep if it's
+/// still running.
+#[no_mangle]
+pub extern "C" fn rust_sleep_interruptible_start(
+    ctx: *const RustCtx,
+    delay_ns: i64,
+) -> *mut RustOp {
+    catch_panic(ptr::null_mut(), || {
+        let op = Op::new();
+        let now = duration_from_ns(delay_ns, "delay").and_then(|_| RustClock::Monotonic.now());
+        let deadline = match now {
+            Ok(now) => now.saturating_add(delay_ns),
+            Err(e) => {
+                op.complete(Err(e));
+                return RustOp::into_raw(op);
+            }
+        };
+        op.on_cancel(move || {
+            let remaining = RustClock::Monotonic.until(deadline).unwrap_or_default();
+            Error::interrupted(remaining.as_nanos().try_into().unwrap_or(i64::MAX))
+        });
+        RustOp::into_raw(ctx::spawn(
+            ctx,
+            op,
+            wait_until(RustClock::Monotonic, deadline),
+        ))
+    })
+}
+
+/// Return the status of a sleep started by `rust_sleep_interruptible_start`,
+/// or `-1` if it's still running. If it was interrupted the status is
+/// `Interrupted` and the nanoseconds that were left of it are written to
+/// `remaining_ns`, while `0` is written if it completed.
+///
+/// # Safety
+///
+/// `remaining_ns` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rust_sleep_interruptible_result(
+    op: *const RustOp,
+    remaining_ns: *mut i64,
+) -> c_int {
+    catch_panic_status(|| op::result::<i64>(op, remaining_ns))
+}
@@ -0,0 +1,83 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+use std::os::raw::c_int;
+use std::ptr;
+use std::time::Duration;
+
+use crate::error::{self, Error};
+use crate::panic::{catch_panic, catch_panic_status};
+use crate::sleep::delay_from_ms;
+
+/// Create a non-blocking timerfd that becomes readable once `delay` has
+/// passed on the monotonic clock.
+fn timerfd(delay: Duration) -> io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::timerfd_create(
+            libc::CLOCK_MONOTONIC,
+            libc::TFD_CLOEXEC | libc::TFD_NONBLOCK,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // An expiration of zero disarms the timer, so the shortest possible delay
+    // is used instead to make the fd readable right away
+    let delay = delay.max(Duration::from_nanos(1));
+    let spec = libc::itimerspec {
+        it_interval: libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        it_value: libc::timespec {
+            tv_sec: delay.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_nsec: delay.subsec_nanos().into(),
+        },
+    };
+    if unsafe { libc::timerfd_settime(fd.as_raw_fd(), 0, &spec, ptr::null_mut()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Return a file descriptor that becomes readable once `delay_ms` milliseconds
+/// have passed, or `-1` on failure in which case the error is reported on the
+/// calling thread. The kernel keeps track of the deadline, so waiting for the
+/// fd in an event loop doesn't involve any threads.
+///
+/// The fd is owned by the caller and must be released using
+/// `rust_sleep_fd_close`, whether or not it has become readable.
+#[no_mangle]
+pub extern "C" fn rust_sleep_fd(delay_ms: c_int) -> c_int {
+    catch_panic(-1, || {
+        let fd = delay_from_ms(delay_ms).and_then(|delay| timerfd(delay).map_err(Error::os));
+        match fd {
+            Ok(fd) => fd.into_raw_fd(),
+            Err(e) => {
+                e.set_last();
+                -1
+            }
+        }
+    })
+}
+
+/// Close a file descriptor returned by `rust_sleep_fd`. Returns `0` on success,
+/// `InvalidArgument` if the fd is negative and `Os` if it could not be closed.
+///
+/// # Safety
+///
+/// `fd` must have been returned by `rust_sleep_fd` and must not have been
+/// closed already.
+#[no_mangle]
+pub unsafe extern "C" fn rust_sleep_fd_close(fd: c_int) -> c_int {
+    catch_panic_status(|| {
+        if fd < 0 {
+            return Error::invalid_argument(format!("invalid fd {}", fd)).set_last();
+        }
+        error::status(match libc::close(fd) {
+            0 => Ok(()),
+            _ => Err(Error::os(io::Error::last_os_error())),
+        })
+    })
+}
@@ -0,0 +1,29 @@
+//! Functions that are only exported with the `testing` feature, for testing
+//! behaviour that can't be triggered through the regular exports.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::time::Duration;
+
+use async_python_ffi_macros::async_ffi;
+
+use crate::backend;
+use crate::error::Error;
+use crate::panic::catch_panic_status;
+
+/// Panic with the given message.
+///
+/// # Safety
+///
+/// `message` must be a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rust_testing_panic(message: *const c_char) -> c_int {
+    catch_panic_status(|| panic!("{}", CStr::from_ptr(message).to_string_lossy()))
+}
+
+/// Panic from within an operation after it has been pending for a while.
+#[async_ffi(name = "rust_testing_panic")]
+async fn panic_async() -> Result<(), Error> {
+    backend::sleep(Duration::from_millis(10)).await?;
+    panic!("operation panicked");
+}
@@ -0,0 +1,275 @@
+//! Clock that only moves when told to, for testing time-based operations
+//! without waiting for them. While it's enabled it replaces every clock the
+//! library reads, and sleeps wait for it instead of the OS. It's only compiled
+//! with the `testing` feature.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::os::raw::c_int;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use crate::clock::RustClock;
+use crate::error::{self, Error};
+use crate::op;
+use crate::panic::{catch_panic, catch_panic_status};
+
+/// How long `rust_clock_advance` waits for the operations that were started
+/// before it to be polled.
+const POLL_TIMEOUT: Duration = Duration::from_secs(5);
+
+const CLOCKS: [RustClock; 3] = [
+    RustClock::Monotonic,
+    RustClock::Boottime,
+    RustClock::Realtime,
+];
+
+struct State {
+    /// Readings of the real clocks when the virtual clock was enabled, or
+    /// `None` if it's disabled.
+    base: Option<[i64; CLOCKS.len()]>,
+    /// Nanoseconds the virtual clock has been advanced since it was enabled.
+    elapsed: i64,
+    /// Wakers of pending sleeps, keyed by their deadline on the virtual
+    /// monotonic clock and a unique id.
+    sleeps: BTreeMap<(i64, u64), Waker>,
+    next_id: u64,
+}
+
+impl State {
+    fn now(&self, clock: RustClock) -> Option<i64> {
+        let base = self.base?[clock as usize];
+        Some(base.saturating_add(self.elapsed))
+    }
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    base: None,
+    elapsed: 0,
+    sleeps: BTreeMap::new(),
+    next_id: 0,
+});
+
+/// Whether the virtual clock is enabled, so the real clocks can be read without
+/// taking the lock.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Notified whenever the virtual clock changes, for blocking sleeps.
+static CHANGED: Condvar = Condvar::new();
+
+/// Return the time of the given clock if the virtual clock is enabled.
+pub fn now(clock: RustClock) -> Option<i64> {
+    if !ENABLED.load(Ordering::Acquire) {
+        return None;
+    }
+    STATE.lock().unwrap().now(clock)
+}
+
+/// Lock held across `fork`, so the virtual clock isn't being changed while the
+/// process is copied.
+pub struct ForkGuard(MutexGuard<'static, State>);
+
+pub fn prepare_fork() -> ForkGuard {
+    ForkGuard(STATE.lock().unwrap())
+}
+
+impl ForkGuard {
+    /// Forget the sleeps in the child, since the tasks waiting for them only
+    /// exist in the parent. Waking or dropping their wakers could run code
+    /// that relies on the parent's workers.
+    pub fn reset(mut self) {
+        std::mem::forget(std::mem::take(&mut self.0.sleeps));
+    }
+}
+
+/// Future returned by `sleep`.
+pub struct Sleep {
+    deadline: i64,
+    key: Option<(i64, u64)>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut state = STATE.lock().unwrap();
+        // Disabling the virtual clock completes every sleep waiting for it
+        let is_due = state
+            .now(RustClock::Monotonic)
+            .is_none_or(|now| now >= self.deadline);
+        if is_due {
+            if let Some(key) = self.key.take() {
+                state.sleeps.remove(&key);
+            }
+            return Poll::Ready(());
+        }
+
+        let key = match self.key {
+            Some(key) => key,
+            None => {
+                state.next_id += 1;
+                let key = (self.deadline, state.next_id);
+                self.key = Some(key);
+                key
+            }
+        };
+        state.sleeps.insert(key, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            STATE.lock().unwrap().sleeps.remove(&key);
+        }
+    }
+}
+
+/// Return a future that completes once the virtual clock has been advanced by
+/// `delay`, or `None` if the virtual clock is disabled.
+pub fn sleep(delay: Duration) -> Option<Sleep> {
+    let now = now(RustClock::Monotonic)?;
+    let delay = delay.as_nanos().try_into().unwrap_or(i64::MAX);
+    Some(Sleep {
+        deadline: now.saturating_add(delay),
+        key: None,
+    })
+}
+
+/// Block the calling thread until `clock` reaches `deadline_ns` on the virtual
+/// clock, or until it's disabled. Returns `false` right away if the virtual
+/// clock is disabled.
+pub fn block_until(clock: RustClock, deadline_ns: i64) -> bool {
+    if !ENABLED.load(Ordering::Acquire) {
+        return false;
+    }
+    let mut state = STATE.lock().unwrap();
+    if state.base.is_none() {
+        return false;
+    }
+    while state.now(clock).is_some_and(|now| now < deadline_ns) {
+        state = CHANGED.wait(state).unwrap();
+    }
+    true
+}
+
+/// Block the calling thread until the virtual clock has been advanced by
+/// `delay`. Returns `false` right away if the virtual clock is disabled.
+pub fn block_for(delay: Duration) -> bool {
+    match now(RustClock::Monotonic) {
+        Some(now) => {
+            let delay = delay.as_nanos().try_into().unwrap_or(i64::MAX);
+            block_until(RustClock::Monotonic, now.saturating_add(delay))
+        }
+        None => false,
+    }
+}
+
+fn set_virtual(enabled: bool) -> Result<(), Error> {
+    let woken = {
+        let mut state = STATE.lock().unwrap();
+        if enabled == state.base.is_some() {
+            return Ok(());
+        }
+        if enabled {
+            let mut base = [0; CLOCKS.len()];
+            for clock in CLOCKS {
+                base[clock as usize] = clock.real_now()?;
+            }
+            state.base = Some(base);
+            state.elapsed = 0;
+        } else {
+            state.base = None;
+        }
+        ENABLED.store(enabled, Ordering::Release);
+        CHANGED.notify_all();
+        std::mem::take(&mut state.sleeps)
+    };
+    woken.into_values().for_each(Waker::wake);
+    Ok(())
+}
+
+fn advance(ns: i64) -> Result<(), Error> {
+    if ns < 0 {
+        return Err(Error::invalid_argument(format!(
+            "the clock can't be moved backwards, got {}",
+            ns
+        )));
+    }
+    // Operations compute their deadlines when they are first polled, so they
+    // must all have been polled for advancing the clock to be deterministic.
+    // That never happens if every worker is blocked waiting for the clock.
+    let unpolled = op::wait_until_polled(POLL_TIMEOUT);
+    if unpolled > 0 {
+        return Err(Error::timeout(format!(
+            "{} operations were not polled within {:?}",
+            unpolled, POLL_TIMEOUT
+        )));
+    }
+
+    let woken = {
+        let mut state = STATE.lock().unwrap();
+        let now = state
+            .now(RustClock::Monotonic)
+            .ok_or_else(|| Error::invalid_state("the virtual clock is not enabled"))?;
+        let now = now.saturating_add(ns);
+        state.elapsed = state.elapsed.saturating_add(ns);
+        CHANGED.notify_all();
+
+        // The sleeps are ordered by deadline, so everything before the first
+        // one that isn't due is
+        let pending = state.sleeps.split_off(&(now.saturating_add(1), 0));
+        std::mem::replace(&mut state.sleeps, pending)
+    };
+    // Sleeps may start new sleeps when woken, so the lock must not be held
+    woken.into_values().for_each(Waker::wake);
+    Ok(())
+}
+
+/// Enable or disable the virtual clock. When enabled, every clock read by the
+/// library stops at its current time and only moves forward when
+/// `rust_clock_advance` is called, and sleeps wait for it instead of real
+/// time. This makes tests of time-based operations fast and deterministic.
+/// Disabling it switches back to the real clocks and wakes up every sleep
+/// that was waiting for the virtual clock.
+///
+/// Sleeps using `rust_sleep_fd` are kept by the kernel, so they always follow
+/// the real clock. Returns `0` on success.
+#[no_mangle]
+pub extern "C" fn rust_clock_set_virtual(enabled: bool) -> c_int {
+    catch_panic_status(|| error::status(set_virtual(enabled)))
+}
+
+/// Move the virtual clock `ns` nanoseconds forward and wake up every sleep
+/// that is due. Operations started before this call are taken into account
+/// even if they have not yet started running, but the woken operations still
+/// complete on the worker pool, so wait for them as usual. This must not be
+/// called from a completion callback since those run on the worker threads.
+///
+/// Returns `0` on success, `InvalidArgument` if `ns` is negative,
+/// `InvalidState` if the virtual clock is not enabled and `Timeout` if the
+/// operations started before this call were not polled within five seconds,
+/// for example because every worker is blocked waiting for the virtual clock.
+/// The clock is not moved in that case.
+#[no_mangle]
+pub extern "C" fn rust_clock_advance(ns: i64) -> c_int {
+    catch_panic_status(|| error::status(advance(ns)))
+}
+
+/// Return the current time of the library's monotonic clock in nanoseconds,
+/// which is the virtual clock if it's enabled. Returns `-1` on failure, in
+/// which case the error is reported on the calling thread.
+#[no_mangle]
+pub extern "C" fn rust_clock_now() -> i64 {
+    catch_panic(-1, || match RustClock::Monotonic.now() {
+        Ok(now) => now,
+        Err(e) => {
+            e.set_last();
+            -1
+        }
+    })
+}
@@ -0,0 +1,100 @@
+"""
+Library under test, loaded the same way as in example.py
+"""
+
+import ctypes
+import os
+import sys
+import time
+from pathlib import Path
+
+ROOT = Path(__file__).parent.parent
+sys.path.insert(0, str(ROOT))
+
+from async_python_ffi import (  # noqa: E402
+    AsyncPythonFfi,
+    RustClock,
+    RustCtxConfig,
+    RustMissedTick,
+    RustPoolConfig,
+)
+from ffilib import cdll_with_spec  # noqa: E402
+
+LIB_PATH = ROOT / "libasync_python_ffi.so"
+LIB = cdll_with_spec(LIB_PATH, AsyncPythonFfi)
+
+# The spec is generated for the features the library was last built with
+HAS_TESTING = hasattr(AsyncPythonFfi, "rust_testing_panic")
+
+# Python's ids for the clocks the library accepts
+CLOCKS = {
+    RustClock.MONOTONIC: time.CLOCK_MONOTONIC,
+    RustClock.BOOTTIME: time.CLOCK_BOOTTIME,
+    RustClock.REALTIME: time.CLOCK_REALTIME,
+}
+
+# Names of the threads started by the library, including the worker pools the
+# tests configure
+THREAD_PREFIXES = ("rust-ffi-", "test-")
+
+
+def library_threads(prefix=THREAD_PREFIXES):
+    names = []
+    for task in os.listdir("/proc/self/task"):
+        try:
+            with open(f"/proc/self/task/{task}/comm") as f:
+                names.append(f.read().strip())
+        except FileNotFoundError:
+            # The thread exited while listing them
+            pass
+    return sorted(name for name in names if name.startswith(prefix))
+
+
+class FfiTestMixin:
+    """
+    Test case mixin that checks that the library's handles were created and
+    frees them after the test
+    """
+
+    def start(self, op):
+        self.assertIsNotNone(op)
+        self.addCleanup(LIB.rust_op_free, op)
+        return op
+
+    def ctx(self, deadline_ns=0, scope=None, tag=0, runtime=None):
+        ctx = LIB.rust_ctx_new(ctypes.byref(RustCtxConfig(deadline_ns, scope, tag, runtime)))
+        self.assertIsNotNone(ctx)
+        self.addCleanup(LIB.rust_ctx_free, ctx)
+        return ctx
+
+    def runtime(self, threads=1, thread_name=b"test-runtime"):
+        config = RustPoolConfig(threads, thread_name)
+        runtime = LIB.rust_runtime_new(ctypes.byref(config))
+        self.assertIsNotNone(runtime)
+        self.addCleanup(LIB.rust_runtime_free, runtime)
+        return runtime
+
+    def scope(self, parent=None):
+        scope = LIB.rust_scope_new(parent)
+        self.assertIsNotNone(scope)
+        self.addCleanup(LIB.rust_scope_free, scope)
+        return scope
+
+    def interval(self, period_ns, phase_ns=0, missed_tick=RustMissedTick.BURST):
+        interval = LIB.rust_interval_new(period_ns, phase_ns, missed_tick)
+        self.assertIsNotNone(interval)
+        self.addCleanup(LIB.rust_interval_free, interval)
+        return interval
+
+    def assertThreads(self, prefix, expected):
+        # Threads name themselves once they have started
+        deadline = time.monotonic() + 1
+        while library_threads(prefix) != expected and time.monotonic() < deadline:
+            time.sleep(0.001)
+        self.assertEqual(library_threads(prefix), expected)
+
+
+def last_error_message():
+    buf = ctypes.create_string_buffer(1024)
+    LIB.rust_last_error_message(buf, len(buf))
+    return buf.value.decode()
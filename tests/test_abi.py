@@ -0,0 +1,23 @@
+import ctypes
+import unittest
+
+from async_python_ffi import ABI_VERSION, ErrorCode, RustPoolConfig
+from ffi import LIB, last_error_message
+
+
+class AbiTest(unittest.TestCase):
+    def test_abi_version(self):
+        self.assertEqual(LIB.rust_ffi_abi_version(), ABI_VERSION)
+
+    def test_struct_size_is_filled_in(self):
+        config = RustPoolConfig(4)
+        self.assertEqual(config.struct_size, ctypes.sizeof(RustPoolConfig))
+        self.assertEqual(config.threads, 4)
+
+    def test_struct_size_mismatch(self):
+        for struct_size in [0, ctypes.sizeof(RustPoolConfig) - 8]:
+            config = RustPoolConfig()
+            config.struct_size = struct_size
+            status = LIB.rust_pool_init(ctypes.byref(config))
+            self.assertEqual(status, ErrorCode.ABI_MISMATCH)
+            self.assertIn("RustPoolConfig", last_error_message())
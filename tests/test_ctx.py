@@ -0,0 +1,75 @@
+import ctypes
+import time
+import unittest
+
+from async_python_ffi import ErrorCode, RustCtxConfig
+from ffi import FfiTestMixin, LIB, last_error_message
+
+MS_NS = 1_000_000
+
+
+class CtxTest(FfiTestMixin, unittest.TestCase):
+    def deadline_in(self, delay_ms):
+        return time.monotonic_ns() + delay_ms * MS_NS
+
+    def test_deadline_times_out_operation(self):
+        ctx = self.ctx(self.deadline_in(50), tag=1234)
+        start = time.monotonic()
+        op = self.start(LIB.rust_sleep_start(ctx, 60_000))
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.TIMEOUT)
+        self.assertIn("1234", last_error_message())
+        self.assertLess(time.monotonic() - start, 1)
+
+    def test_operation_completing_before_deadline_succeeds(self):
+        ctx = self.ctx(self.deadline_in(60_000))
+        op = self.start(LIB.rust_sleep_start(ctx, 10))
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+
+    def test_passed_deadline_fails_right_away(self):
+        ctx = self.ctx(self.deadline_in(-1000))
+        op = self.start(LIB.rust_sleep_start(ctx, 0))
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.TIMEOUT)
+
+    def test_deadline_applies_to_every_operation(self):
+        ctx = self.ctx(self.deadline_in(50))
+        interval = self.interval(60_000 * MS_NS, 60_000 * MS_NS)
+        ops = [
+            self.start(LIB.rust_sleep_until_start(ctx, 2**62, 0)),
+            self.start(LIB.rust_sleep_interruptible_start(ctx, 60_000 * MS_NS)),
+            self.start(LIB.rust_interval_next(ctx, interval)),
+        ]
+        for op in ops:
+            self.assertEqual(LIB.rust_op_wait(op), ErrorCode.TIMEOUT)
+
+    def test_scope_cancels_operations(self):
+        scope = self.scope()
+        ctx = self.ctx(scope=scope)
+        ops = [self.start(LIB.rust_sleep_start(ctx, 60_000)) for _ in range(3)]
+        LIB.rust_scope_cancel(scope)
+        for op in ops:
+            self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+    def test_operations_outlive_freed_context(self):
+        ctx = LIB.rust_ctx_new(ctypes.byref(RustCtxConfig(self.deadline_in(50))))
+        op = self.start(LIB.rust_sleep_start(ctx, 60_000))
+        LIB.rust_ctx_free(ctx)
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.TIMEOUT)
+
+    def test_null_context_has_no_limits(self):
+        ctx = LIB.rust_ctx_new(None)
+        self.addCleanup(LIB.rust_ctx_free, ctx)
+        self.assertEqual(LIB.rust_ctx_tag(ctx), 0)
+        for ctx in [ctx, None]:
+            op = self.start(LIB.rust_sleep_start(ctx, 10))
+            self.assertEqual(LIB.rust_op_wait(op), 0)
+
+    def test_tag(self):
+        self.assertEqual(LIB.rust_ctx_tag(self.ctx(tag=2**64 - 1)), 2**64 - 1)
+        self.assertEqual(LIB.rust_ctx_tag(None), 0)
+
+    def test_rejects_wrong_struct_size(self):
+        config = RustCtxConfig()
+        config.struct_size = 8
+        self.assertIsNone(LIB.rust_ctx_new(ctypes.byref(config)))
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.ABI_MISMATCH)
+        self.assertIn("RustCtxConfig", last_error_message())
@@ -0,0 +1,194 @@
+import ctypes
+import os
+import select
+import signal
+import threading
+import time
+import traceback
+import unittest
+
+from async_python_ffi import (
+    ErrorCode,
+    RustCompletion,
+    RustCtxConfig,
+    RustFfiReport,
+    RustMissedTick,
+)
+from ffi import LIB, FfiTestMixin, last_error_message
+
+
+class ForkTest(FfiTestMixin, unittest.TestCase):
+    def run_in_child(self, f):
+        pid = os.fork()
+        if pid == 0:
+            status = 0
+            try:
+                f()
+            except BaseException:
+                traceback.print_exc()
+                status = 1
+            os._exit(status)
+
+        # A deadlocked child would otherwise hang the test
+        deadline = time.monotonic() + 10
+        while time.monotonic() < deadline:
+            waited, status = os.waitpid(pid, os.WNOHANG)
+            if waited:
+                self.assertEqual(os.waitstatus_to_exitcode(status), 0)
+                return
+            time.sleep(0.01)
+        os.kill(pid, signal.SIGKILL)
+        os.waitpid(pid, 0)
+        self.fail("child process didn't exit")
+
+    def test_child_can_start_operations(self):
+        parent_op = self.start(LIB.rust_sleep_start(None, 60_000))
+
+        def child():
+            op = LIB.rust_sleep_start(None, 10)
+            assert LIB.rust_op_wait(op) == 0
+            LIB.rust_op_free(op)
+
+        self.run_in_child(child)
+        self.assertEqual(LIB.rust_op_poll(parent_op), -1)
+        LIB.rust_op_cancel(parent_op)
+        self.assertEqual(LIB.rust_op_wait(parent_op), ErrorCode.CANCELLED)
+
+    def test_inherited_operations_are_cancelled_in_child(self):
+        ops = [self.start(LIB.rust_sleep_start(None, 60_000)) for _ in range(3)]
+
+        def child():
+            for op in ops:
+                assert LIB.rust_op_wait(op) == ErrorCode.CANCELLED
+                assert "forked" in last_error_message()
+            report = RustFfiReport()
+            assert LIB.rust_ffi_shutdown(1000, ctypes.byref(report)) == 0
+            assert report.cancelled == 0
+
+        self.run_in_child(child)
+        for op in ops:
+            self.assertEqual(LIB.rust_op_poll(op), -1)
+
+    def test_child_can_use_runtime(self):
+        runtime = self.runtime()
+        ctx = self.ctx(runtime=runtime)
+        parent_op = self.start(LIB.rust_sleep_start(ctx, 60_000))
+
+        def child():
+            assert LIB.rust_op_wait(parent_op) == ErrorCode.CANCELLED
+            op = LIB.rust_sleep_start(ctx, 10)
+            assert LIB.rust_op_wait(op) == 0
+            LIB.rust_op_free(op)
+            assert LIB.rust_runtime_free(runtime) == 0
+
+        self.run_in_child(child)
+        self.assertEqual(LIB.rust_op_poll(parent_op), -1)
+
+    def test_fork_while_runtime_starts_operations(self):
+        runtime = self.runtime()
+        ctx = self.ctx(runtime=runtime)
+        stop = threading.Event()
+
+        def run_sleeps():
+            while not stop.is_set():
+                op = LIB.rust_sleep_start(ctx, 0)
+                LIB.rust_op_wait(op)
+                LIB.rust_op_free(op)
+
+        def child():
+            op = LIB.rust_sleep_start(ctx, 1)
+            assert LIB.rust_op_wait(op) == 0
+            LIB.rust_op_free(op)
+
+        thread = threading.Thread(target=run_sleeps)
+        thread.start()
+        try:
+            for _ in range(20):
+                self.run_in_child(child)
+        finally:
+            stop.set()
+            thread.join()
+
+    def test_child_has_its_own_completion_queue(self):
+        fd = LIB.rust_cq_fd()
+        parent_op = self.start(LIB.rust_sleep_submit(None, 50))
+        buf = (RustCompletion * 8)()
+
+        def child():
+            child_fd = LIB.rust_cq_fd()
+            op = LIB.rust_sleep_submit(None, 100)
+            readable, _, _ = select.select([child_fd], [], [], 5)
+            assert readable == [child_fd]
+            n = LIB.rust_cq_drain(buf, len(buf))
+            assert [record.op_id for record in buf[:n]] == [LIB.rust_op_id(op)]
+
+        self.run_in_child(child)
+        select.select([fd], [], [], 5)
+        n = LIB.rust_cq_drain(buf, len(buf))
+        self.assertEqual([record.op_id for record in buf[:n]], [LIB.rust_op_id(parent_op)])
+
+    def test_fork_while_operations_complete(self):
+        stop = threading.Event()
+
+        def run_sleeps():
+            while not stop.is_set():
+                ops = [LIB.rust_sleep_start(None, 0) for _ in range(10)]
+                for op in ops:
+                    LIB.rust_op_wait(op)
+                    LIB.rust_op_free(op)
+
+        def child():
+            op = LIB.rust_sleep_start(None, 1)
+            assert LIB.rust_op_wait(op) == 0
+            LIB.rust_op_free(op)
+
+        thread = threading.Thread(target=run_sleeps)
+        thread.start()
+        try:
+            for _ in range(20):
+                self.run_in_child(child)
+        finally:
+            stop.set()
+            thread.join()
+
+    def test_fork_while_handles_are_created(self):
+        stop = threading.Event()
+
+        def create_handles():
+            while not stop.is_set():
+                scope = LIB.rust_scope_new(None)
+                ctx = LIB.rust_ctx_new(ctypes.byref(RustCtxConfig(0, scope, 0)))
+                interval = LIB.rust_interval_new(1_000_000, 0, RustMissedTick.BURST)
+                op = LIB.rust_interval_next(ctx, interval)
+                LIB.rust_scope_cancel(scope)
+                LIB.rust_op_wait(op)
+                LIB.rust_op_free(op)
+                LIB.rust_interval_free(interval)
+                LIB.rust_ctx_free(ctx)
+                LIB.rust_scope_free(scope)
+
+        def child():
+            scope = LIB.rust_scope_new(None)
+            ctx = LIB.rust_ctx_new(ctypes.byref(RustCtxConfig(0, scope, 0)))
+            interval = LIB.rust_interval_new(1_000_000, 0, RustMissedTick.BURST)
+            op = LIB.rust_interval_next(ctx, interval)
+            assert LIB.rust_op_wait(op) == 0
+            assert LIB.rust_scope_cancel(scope) == 0
+            for free, handle in [
+                (LIB.rust_op_free, op),
+                (LIB.rust_interval_free, interval),
+                (LIB.rust_ctx_free, ctx),
+                (LIB.rust_scope_free, scope),
+            ]:
+                assert free(handle) == 0
+
+        threads = [threading.Thread(target=create_handles) for _ in range(2)]
+        for thread in threads:
+            thread.start()
+        try:
+            for _ in range(20):
+                self.run_in_child(child)
+        finally:
+            stop.set()
+            for thread in threads:
+                thread.join()
@@ -0,0 +1,81 @@
+import ctypes
+import unittest
+
+from async_python_ffi import ErrorCode, RustMissedTick
+from ffi import FfiTestMixin, LIB, last_error_message
+
+
+class HandleTest(FfiTestMixin, unittest.TestCase):
+    def freed_op(self):
+        op = LIB.rust_sleep_start(None, 0)
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+        self.assertEqual(LIB.rust_op_free(op), 0)
+        return op
+
+    def assertInvalidHandle(self, status):
+        self.assertEqual(status, ErrorCode.INVALID_HANDLE)
+        self.assertIn("freed", last_error_message())
+
+    def test_double_free_is_reported(self):
+        op = self.freed_op()
+        self.assertInvalidHandle(LIB.rust_op_free(op))
+        self.assertInvalidHandle(LIB.rust_sleep_free(op))
+
+    def test_freed_operation_is_reported(self):
+        op = self.freed_op()
+        self.assertInvalidHandle(LIB.rust_op_poll(op))
+        self.assertInvalidHandle(LIB.rust_op_wait(op))
+        self.assertInvalidHandle(LIB.rust_op_cancel(op))
+        self.assertInvalidHandle(LIB.rust_sleep_result(op))
+        self.assertInvalidHandle(LIB.rust_sleep_interruptible_result(op, None))
+        self.assertEqual(LIB.rust_op_id(op), 0)
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_HANDLE)
+
+    def test_stale_handle_doesnt_refer_to_reused_slot(self):
+        stale = self.freed_op()
+        # The freed slot is reused, but with a new generation
+        op = self.start(LIB.rust_sleep_start(None, 60_000))
+        self.assertNotEqual(op, stale)
+        self.assertInvalidHandle(LIB.rust_op_cancel(stale))
+        self.assertEqual(LIB.rust_op_poll(op), -1)
+
+    def test_unknown_handle_is_reported(self):
+        for handle in [1, 12345, 2**64 - 1]:
+            self.assertEqual(LIB.rust_op_poll(handle), ErrorCode.INVALID_HANDLE)
+            self.assertEqual(LIB.rust_op_free(handle), ErrorCode.INVALID_HANDLE)
+
+    def test_null_handles(self):
+        self.assertEqual(LIB.rust_op_poll(None), ErrorCode.INVALID_ARGUMENT)
+        frees = [LIB.rust_op_free, LIB.rust_scope_free, LIB.rust_interval_free, LIB.rust_ctx_free]
+        for free in frees:
+            self.assertEqual(free(None), 0)
+
+    def test_freed_scope_is_reported(self):
+        scope = LIB.rust_scope_new(None)
+        self.assertEqual(LIB.rust_scope_free(scope), 0)
+        self.assertInvalidHandle(LIB.rust_scope_free(scope))
+        self.assertInvalidHandle(LIB.rust_scope_cancel(scope))
+        self.assertFalse(LIB.rust_scope_is_cancelled(scope))
+        self.assertIsNone(LIB.rust_scope_new(scope))
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_HANDLE)
+
+        op = self.start(LIB.rust_sleep_start(None, 0))
+        self.assertInvalidHandle(LIB.rust_scope_add(scope, op))
+
+    def test_freed_interval_is_reported(self):
+        interval = LIB.rust_interval_new(1_000_000, 0, RustMissedTick.BURST)
+        self.assertEqual(LIB.rust_interval_free(interval), 0)
+        self.assertInvalidHandle(LIB.rust_interval_free(interval))
+
+        op = self.start(LIB.rust_interval_next(None, interval))
+        tick = ctypes.c_int64()
+        self.assertInvalidHandle(LIB.rust_interval_next_result(op, ctypes.byref(tick)))
+
+    def test_freed_context_is_reported(self):
+        ctx = LIB.rust_ctx_new(None)
+        self.assertEqual(LIB.rust_ctx_free(ctx), 0)
+        self.assertInvalidHandle(LIB.rust_ctx_free(ctx))
+        self.assertEqual(LIB.rust_ctx_tag(ctx), 0)
+
+        op = self.start(LIB.rust_sleep_start(ctx, 0))
+        self.assertInvalidHandle(LIB.rust_op_wait(op))
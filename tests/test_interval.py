@@ -0,0 +1,104 @@
+import ctypes
+import time
+import unittest
+
+from async_python_ffi import ErrorCode, RustCallback, RustMissedTick
+from ffi import FfiTestMixin, HAS_TESTING, LIB
+
+PERIOD_NS = 20_000_000
+
+
+class IntervalTest(FfiTestMixin, unittest.TestCase):
+    def next_tick(self, interval, advance_ns=0):
+        """
+        Wait for the next tick and return when it was scheduled for, advancing
+        the virtual clock by `advance_ns` first if it's enabled
+        """
+        op = self.start(LIB.rust_interval_next(None, interval))
+        if advance_ns:
+            LIB.rust_clock_advance(advance_ns)
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+        tick = ctypes.c_int64()
+        LIB.rust_interval_next_result(op, ctypes.byref(tick))
+        return tick.value
+
+    def test_ticks_follow_schedule(self):
+        interval = self.interval(PERIOD_NS)
+        first = self.next_tick(interval)
+        for i in range(1, 10):
+            tick = self.next_tick(interval)
+            self.assertEqual(tick, first + i * PERIOD_NS)
+            self.assertGreaterEqual(time.monotonic_ns(), tick)
+
+    def test_phase_delays_first_tick(self):
+        start = time.monotonic_ns()
+        interval = self.interval(PERIOD_NS, phase_ns=50_000_000)
+        self.assertGreaterEqual(self.next_tick(interval), start + 50_000_000)
+        self.assertGreaterEqual(time.monotonic_ns(), start + 50_000_000)
+
+    def test_burst_catches_up_with_missed_ticks(self):
+        interval = self.interval(PERIOD_NS, missed_tick=RustMissedTick.BURST)
+        first = self.next_tick(interval)
+        time.sleep(0.11)
+        start = time.monotonic()
+        for i in range(1, 5):
+            self.assertEqual(self.next_tick(interval), first + i * PERIOD_NS)
+        self.assertLess(time.monotonic() - start, 0.05)
+
+    @unittest.skipUnless(HAS_TESTING, "requires building with --features testing")
+    def test_delay_shifts_schedule(self):
+        # Real sleeps can oversleep by more than a period on a busy machine,
+        # which would make the late tick miss its slot
+        self.assertEqual(LIB.rust_clock_set_virtual(True), 0)
+        self.addCleanup(LIB.rust_clock_set_virtual, False)
+        interval = self.interval(PERIOD_NS, missed_tick=RustMissedTick.DELAY)
+        first = self.next_tick(interval)
+        LIB.rust_clock_advance(110_000_000)
+        late = self.next_tick(interval)
+        self.assertEqual(late, first + PERIOD_NS)
+        consumed = LIB.rust_clock_now()
+        tick = self.next_tick(interval, advance_ns=PERIOD_NS)
+        self.assertEqual(tick, consumed + PERIOD_NS)
+        self.assertEqual(self.next_tick(interval, advance_ns=PERIOD_NS), tick + PERIOD_NS)
+
+    def test_skip_drops_missed_ticks(self):
+        interval = self.interval(PERIOD_NS, missed_tick=RustMissedTick.SKIP)
+        first = self.next_tick(interval)
+        time.sleep(0.11)
+        self.next_tick(interval)
+        consumed = time.monotonic_ns()
+        tick = self.next_tick(interval)
+        self.assertEqual((tick - first) % PERIOD_NS, 0)
+        self.assertGreaterEqual(tick, first + 6 * PERIOD_NS)
+        self.assertLessEqual(tick, consumed + PERIOD_NS)
+
+    def test_cancelled_wait_does_not_consume_tick(self):
+        start = time.monotonic_ns()
+        interval = self.interval(PERIOD_NS, phase_ns=50_000_000)
+        op = self.start(LIB.rust_interval_next(None, interval))
+        LIB.rust_op_cancel(op)
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+        tick = self.next_tick(interval)
+        self.assertGreaterEqual(tick, start + 50_000_000)
+        self.assertLess(tick, start + 50_000_000 + PERIOD_NS)
+
+    def test_only_one_tick_can_be_waited_for(self):
+        interval = self.interval(PERIOD_NS, phase_ns=1_000_000_000)
+        first = self.start(LIB.rust_interval_next(None, interval))
+        second = self.start(LIB.rust_interval_next(None, interval))
+        self.assertEqual(LIB.rust_op_wait(second), ErrorCode.INVALID_STATE)
+        self.assertEqual(LIB.rust_op_poll(first), -1)
+
+    def test_next_callback_rejects_null_callback(self):
+        interval = self.interval(1_000_000)
+        self.assertIsNone(LIB.rust_interval_next_callback(None, interval, RustCallback(), None))
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_ARGUMENT)
+        # The interval can still wait for a tick
+        self.next_tick(interval)
+
+    def test_rejects_invalid_arguments(self):
+        for period, phase, missed_tick in [(0, 0, 0), (-1, 0, 0), (1, -1, 0), (1, 0, 3)]:
+            with self.subTest(period=period, phase=phase, missed_tick=missed_tick):
+                self.assertIsNone(LIB.rust_interval_new(period, phase, missed_tick))
+                self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_ARGUMENT)
@@ -0,0 +1,91 @@
+import ctypes
+import unittest
+
+from async_python_ffi import (
+    ErrorCode,
+    RustFfiConfig,
+    RustFfiReport,
+    RustMissedTick,
+    RustPoolConfig,
+)
+from ffi import THREAD_PREFIXES, FfiTestMixin, LIB, last_error_message, library_threads
+
+
+class LifecycleTest(FfiTestMixin, unittest.TestCase):
+    def setUp(self):
+        self.assertEqual(self.shutdown(-1).struct_size, ctypes.sizeof(RustFfiReport))
+
+    def shutdown(self, timeout_ms):
+        report = RustFfiReport()
+        self.assertEqual(LIB.rust_ffi_shutdown(timeout_ms, ctypes.byref(report)), 0)
+        return report
+
+    def test_init_starts_configured_pool(self):
+        pool = RustPoolConfig(2, b"test-worker")
+        self.assertEqual(LIB.rust_ffi_init(ctypes.byref(RustFfiConfig(ctypes.pointer(pool)))), 0)
+        self.assertThreads(THREAD_PREFIXES, ["test-worker-0", "test-worker-1"])
+
+        self.assertEqual(LIB.rust_ffi_init(None), ErrorCode.INVALID_STATE)
+        self.shutdown(0)
+        self.assertThreads(THREAD_PREFIXES, [])
+        self.assertEqual(LIB.rust_ffi_init(None), 0)
+
+    def test_shutdown_stops_every_thread(self):
+        op = LIB.rust_sleep_start(None, 10)
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+        LIB.rust_op_free(op)
+        self.assertNotEqual(library_threads(), [])
+        self.shutdown(0)
+        self.assertThreads(THREAD_PREFIXES, [])
+
+    def test_shutdown_drains_operations(self):
+        op = self.start(LIB.rust_sleep_start(None, 50))
+        report = self.shutdown(10_000)
+        self.assertEqual(report.cancelled, 0)
+        self.assertEqual(LIB.rust_op_poll(op), 0)
+
+    def test_shutdown_cancels_operations_after_timeout(self):
+        ops = [self.start(LIB.rust_sleep_start(None, 60_000)) for _ in range(3)]
+        report = self.shutdown(10)
+        self.assertEqual(report.cancelled, 3)
+        for op in ops:
+            self.assertEqual(LIB.rust_op_poll(op), ErrorCode.CANCELLED)
+
+    def test_report_counts_leaked_handles(self):
+        before = self.shutdown(0)
+        scope = LIB.rust_scope_new(None)
+        ctx = LIB.rust_ctx_new(None)
+        interval = LIB.rust_interval_new(1_000_000, 0, RustMissedTick.BURST)
+        ops = [LIB.rust_sleep_start(ctx, 0) for _ in range(2)]
+        for op in ops:
+            LIB.rust_op_wait(op)
+
+        report = self.shutdown(0)
+        self.assertEqual(report.ops - before.ops, 2)
+        self.assertEqual(report.scopes - before.scopes, 1)
+        self.assertEqual(report.intervals - before.intervals, 1)
+        self.assertEqual(report.contexts - before.contexts, 1)
+
+        # The handles can still be freed after shutting down
+        for op in ops:
+            self.assertEqual(LIB.rust_op_free(op), 0)
+        self.assertEqual(LIB.rust_scope_free(scope), 0)
+        self.assertEqual(LIB.rust_ctx_free(ctx), 0)
+        self.assertEqual(LIB.rust_interval_free(interval), 0)
+        self.assertEqual(self.shutdown(0).ops, before.ops)
+
+    def test_library_restarts_after_shutdown(self):
+        self.shutdown(0)
+        op = self.start(LIB.rust_sleep_start(None, 10))
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+
+    def test_struct_size_mismatch(self):
+        config = RustFfiConfig()
+        config.struct_size = 8
+        self.assertEqual(LIB.rust_ffi_init(ctypes.byref(config)), ErrorCode.ABI_MISMATCH)
+        self.assertIn("RustFfiConfig", last_error_message())
+
+        report = RustFfiReport()
+        report.struct_size = 8
+        self.assertEqual(LIB.rust_ffi_shutdown(0, ctypes.byref(report)), ErrorCode.ABI_MISMATCH)
+        self.assertIn("RustFfiReport", last_error_message())
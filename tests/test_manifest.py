@@ -0,0 +1,67 @@
+import ctypes
+import unittest
+
+from async_python_ffi import ABI_VERSION, AsyncPythonFfi, RustFfiKind
+from ffi import LIB, LIB_PATH
+from ffilib import cdll_with_spec, ManifestError, read_manifest
+
+
+class ManifestTest(unittest.TestCase):
+    def test_manifest_matches_generated_spec(self):
+        abi_version, functions = read_manifest(LIB, "rust_ffi_manifest")
+        self.assertEqual(abi_version, ABI_VERSION)
+        spec_names = {
+            name for name in vars(AsyncPythonFfi) if name.startswith("rust_")
+        }
+        self.assertEqual(spec_names, set(functions))
+
+    def test_manifest_describes_kind(self):
+        _, functions = read_manifest(LIB, "rust_ffi_manifest")
+        kinds = {name: kind for name, (_, _, kind) in functions.items()}
+        self.assertEqual(kinds["rust_sleep"], RustFfiKind.BLOCKING)
+        self.assertEqual(kinds["rust_sleep_start"], RustFfiKind.ASYNC)
+        self.assertEqual(kinds["rust_sleep_submit"], RustFfiKind.ASYNC)
+        self.assertEqual(kinds["rust_op_poll"], RustFfiKind.IMMEDIATE)
+
+    def test_load_without_spec(self):
+        lib = cdll_with_spec(LIB_PATH, manifest="rust_ffi_manifest")
+        self.assertEqual(lib.rust_sleep(0), 0)
+        op = lib.rust_sleep_start(None, 0)
+        try:
+            self.assertEqual(lib.rust_op_wait(op), 0)
+        finally:
+            lib.rust_op_free(op)
+
+    def test_load_with_spec(self):
+        lib = cdll_with_spec(
+            LIB_PATH,
+            AsyncPythonFfi,
+            manifest="rust_ffi_manifest",
+            abi_version=ABI_VERSION,
+        )
+        self.assertEqual(lib.rust_sleep(0), 0)
+
+    def test_abi_version_mismatch(self):
+        with self.assertRaises(ManifestError):
+            cdll_with_spec(
+                LIB_PATH,
+                AsyncPythonFfi,
+                manifest="rust_ffi_manifest",
+                abi_version=ABI_VERSION + 1,
+            )
+
+    def test_signature_mismatch(self):
+        class Spec:
+            def rust_sleep(delay_ms: ctypes.c_int64) -> ctypes.c_int:
+                ...
+
+        with self.assertRaises(ManifestError):
+            cdll_with_spec(LIB_PATH, Spec, manifest="rust_ffi_manifest")
+
+    def test_missing_function(self):
+        class Spec:
+            def rust_does_not_exist() -> ctypes.c_int:
+                ...
+
+        with self.assertRaises(ManifestError):
+            cdll_with_spec(LIB_PATH, Spec, manifest="rust_ffi_manifest")
@@ -0,0 +1,29 @@
+import unittest
+
+from async_python_ffi import ErrorCode
+from ffi import FfiTestMixin, HAS_TESTING, LIB, last_error_message
+
+
+@unittest.skipUnless(HAS_TESTING, "requires building with --features testing")
+class PanicTest(FfiTestMixin, unittest.TestCase):
+    def test_panic_is_reported_as_error(self):
+        self.assertEqual(LIB.rust_testing_panic(b"boom"), ErrorCode.PANIC)
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.PANIC)
+        self.assertEqual(last_error_message(), "panic: boom")
+
+    def test_library_is_usable_after_panic(self):
+        LIB.rust_testing_panic(b"boom")
+        self.assertEqual(LIB.rust_sleep(0), 0)
+
+    def test_operation_panic_fails_operation(self):
+        op = self.start(LIB.rust_testing_panic_start(None))
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.PANIC)
+        self.assertEqual(last_error_message(), "panic: operation panicked")
+
+    def test_executor_survives_operation_panic(self):
+        ops = [self.start(LIB.rust_testing_panic_start(None)) for _ in range(16)]
+        for op in ops:
+            self.assertEqual(LIB.rust_op_wait(op), ErrorCode.PANIC)
+
+        op = self.start(LIB.rust_sleep_start(None, 10))
+        self.assertEqual(LIB.rust_op_wait(op), 0)
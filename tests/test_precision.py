@@ -0,0 +1,50 @@
+import time
+import unittest
+
+from async_python_ffi import ErrorCode
+from ffi import LIB, last_error_message
+
+
+def overshoot(sleep, delay_ns):
+    """
+    Return how late a sleep of delay_ns completed, in nanoseconds
+    """
+    start = time.monotonic_ns()
+    if sleep(delay_ns) != 0:
+        raise AssertionError(last_error_message())
+    return time.monotonic_ns() - start - delay_ns
+
+
+class PrecisionTest(unittest.TestCase):
+    # How late the sleeps complete depends on the machine, so only their order
+    # is checked here. benches/sleep_accuracy.py --check has the thresholds.
+    def test_precise_sleep_is_not_later_than_sleep(self):
+        modes = {
+            "rust_sleep": lambda ns: LIB.rust_sleep(ns // 1_000_000),
+            "precise": lambda ns: LIB.rust_sleep_precise(ns, 0),
+            "precise with slack": lambda ns: LIB.rust_sleep_precise(ns, 500_000),
+        }
+        overshoots = {name: [] for name in modes}
+        # The modes take turns so they are equally affected by the load on the
+        # machine while the test is running
+        for i in range(30):
+            for name, sleep in modes.items():
+                overshoots[name].append(overshoot(sleep, (1 + i % 2) * 1_000_000))
+        for name, values in overshoots.items():
+            # The overshoots are included in the message, so a failure shows
+            # how far off the sleeps were
+            self.assertGreaterEqual(min(values), 0, (name, values))
+        median = {name: sorted(values)[len(values) // 2] for name, values in overshoots.items()}
+        self.assertLessEqual(median["precise"], median["rust_sleep"], median)
+        self.assertLessEqual(median["precise with slack"], median["rust_sleep"], median)
+
+    def test_precise_sleep_with_zero_delay_returns_right_away(self):
+        start = time.monotonic_ns()
+        self.assertEqual(LIB.rust_sleep_precise(0, 0), 0)
+        self.assertLess(time.monotonic_ns() - start, 1_000_000)
+
+    def test_precise_sleep_rejects_negative_arguments(self):
+        self.assertEqual(LIB.rust_sleep_precise(-1, 0), ErrorCode.INVALID_ARGUMENT)
+        self.assertIn("delay", last_error_message())
+        self.assertEqual(LIB.rust_sleep_precise(0, -1), ErrorCode.INVALID_ARGUMENT)
+        self.assertIn("slack", last_error_message())
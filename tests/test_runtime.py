@@ -0,0 +1,93 @@
+import ctypes
+import threading
+import unittest
+
+from async_python_ffi import ErrorCode, RustCallback, RustCtxConfig, RustFfiReport, RustPoolConfig
+from ffi import FfiTestMixin, LIB, last_error_message
+
+
+class RuntimeTest(FfiTestMixin, unittest.TestCase):
+    def test_operations_run_on_runtime_workers(self):
+        ctx = self.ctx(runtime=self.runtime(thread_name=b"test-callback"))
+        done = threading.Event()
+        names = []
+
+        @RustCallback
+        def callback(user_data, status):
+            with open("/proc/thread-self/comm") as f:
+                names.append(f.read().strip())
+            done.set()
+
+        self.start(LIB.rust_sleep_callback(ctx, 0, callback, None))
+        self.assertTrue(done.wait(5))
+        self.assertEqual(names, ["test-callback-0"])
+
+    def test_free_only_cancels_runtime_operations(self):
+        first = LIB.rust_runtime_new(None)
+        first_op = self.start(LIB.rust_sleep_start(self.ctx(runtime=first), 60_000))
+        second_op = self.start(LIB.rust_sleep_start(self.ctx(runtime=self.runtime()), 60_000))
+        global_op = self.start(LIB.rust_sleep_start(None, 60_000))
+
+        self.assertEqual(LIB.rust_runtime_free(first), 0)
+        self.assertEqual(LIB.rust_op_poll(first_op), ErrorCode.CANCELLED)
+        self.assertEqual(LIB.rust_op_poll(second_op), -1)
+        self.assertEqual(LIB.rust_op_poll(global_op), -1)
+
+    def test_free_stops_runtime_workers(self):
+        runtime = LIB.rust_runtime_new(ctypes.byref(RustPoolConfig(2, b"test-freed")))
+        self.assertThreads("test-freed", ["test-freed-0", "test-freed-1"])
+        self.assertEqual(LIB.rust_runtime_free(runtime), 0)
+        self.assertThreads("test-freed", [])
+
+    def test_free_from_completion_callback(self):
+        runtime = LIB.rust_runtime_new(ctypes.byref(RustPoolConfig(1, b"test-self-free")))
+        ctx = self.ctx(runtime=runtime)
+        done = threading.Event()
+        statuses = []
+
+        @RustCallback
+        def callback(user_data, status):
+            statuses.append(LIB.rust_runtime_free(runtime))
+            done.set()
+
+        self.start(LIB.rust_sleep_callback(ctx, 0, callback, None))
+        self.assertTrue(done.wait(5))
+        self.assertEqual(statuses, [0])
+        self.assertThreads("test-self-free", [])
+
+    def test_freed_runtime_fails_operations(self):
+        runtime = LIB.rust_runtime_new(None)
+        ctx = self.ctx(runtime=runtime)
+        LIB.rust_runtime_free(runtime)
+
+        op = self.start(LIB.rust_sleep_start(ctx, 0))
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.INVALID_STATE)
+        self.assertIn("freed", last_error_message())
+
+        self.assertIsNone(LIB.rust_ctx_new(ctypes.byref(RustCtxConfig(0, None, 0, runtime))))
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_HANDLE)
+        self.assertEqual(LIB.rust_runtime_free(runtime), ErrorCode.INVALID_HANDLE)
+        self.assertEqual(LIB.rust_runtime_free(None), 0)
+
+    def test_shutdown_stops_runtimes_until_next_operation(self):
+        ctx = self.ctx(runtime=self.runtime(thread_name=b"test-restart"))
+        LIB.rust_ffi_shutdown(0, None)
+        self.assertThreads("test-restart", [])
+
+        op = self.start(LIB.rust_sleep_start(ctx, 0))
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+        self.assertThreads("test-restart", ["test-restart-0"])
+
+        report = RustFfiReport()
+        LIB.rust_ffi_shutdown(0, ctypes.byref(report))
+        self.assertGreaterEqual(report.runtimes, 1)
+
+    def test_invalid_config(self):
+        config = RustPoolConfig()
+        config.struct_size = 8
+        self.assertIsNone(LIB.rust_runtime_new(ctypes.byref(config)))
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.ABI_MISMATCH)
+        self.assertIn("RustPoolConfig", last_error_message())
+
+        self.assertIsNone(LIB.rust_runtime_new(ctypes.byref(RustPoolConfig(1, b"\xff"))))
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_ARGUMENT)
@@ -0,0 +1,71 @@
+import unittest
+
+from async_python_ffi import ErrorCode
+from ffi import FfiTestMixin, LIB
+
+
+class ScopeTest(FfiTestMixin, unittest.TestCase):
+    def sleep(self, scope, delay_ms=60_000):
+        op = self.start(LIB.rust_sleep_start(None, delay_ms))
+        self.assertEqual(LIB.rust_scope_add(scope, op), 0)
+        return op
+
+    def test_cancel_cancels_every_operation(self):
+        scope = self.scope()
+        ops = [self.sleep(scope) for _ in range(10)]
+        self.assertFalse(LIB.rust_scope_is_cancelled(scope))
+        LIB.rust_scope_cancel(scope)
+        self.assertTrue(LIB.rust_scope_is_cancelled(scope))
+        for op in ops:
+            self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+    def test_cancel_cancels_nested_scopes(self):
+        parent = self.scope()
+        child = self.scope(parent)
+        grandchild = self.scope(child)
+        ops = [self.sleep(scope) for scope in [parent, child, grandchild]]
+        LIB.rust_scope_cancel(parent)
+        for scope in [child, grandchild]:
+            self.assertTrue(LIB.rust_scope_is_cancelled(scope))
+        for op in ops:
+            self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+    def test_cancelling_nested_scope_leaves_parent_alone(self):
+        parent = self.scope()
+        child = self.scope(parent)
+        parent_op = self.sleep(parent, 50)
+        child_op = self.sleep(child)
+        LIB.rust_scope_cancel(child)
+        self.assertEqual(LIB.rust_op_wait(child_op), ErrorCode.CANCELLED)
+        self.assertFalse(LIB.rust_scope_is_cancelled(parent))
+        self.assertEqual(LIB.rust_op_wait(parent_op), 0)
+
+    def test_adding_to_cancelled_scope_cancels_right_away(self):
+        scope = self.scope()
+        LIB.rust_scope_cancel(scope)
+        op = self.sleep(scope)
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+        child = self.scope(scope)
+        self.assertTrue(LIB.rust_scope_is_cancelled(child))
+        op = self.sleep(child)
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+    def test_completed_operations_are_not_affected(self):
+        scope = self.scope()
+        op = self.sleep(scope, 0)
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+        LIB.rust_scope_cancel(scope)
+        self.assertEqual(LIB.rust_op_poll(op), 0)
+
+    def test_free_cancels_operations(self):
+        scope = LIB.rust_scope_new(None)
+        op = self.sleep(scope)
+        LIB.rust_scope_free(scope)
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+    def test_add_rejects_null_handles(self):
+        scope = self.scope()
+        self.assertEqual(LIB.rust_scope_add(scope, None), ErrorCode.INVALID_ARGUMENT)
+        op = self.sleep(scope, 0)
+        self.assertEqual(LIB.rust_scope_add(None, op), ErrorCode.INVALID_ARGUMENT)
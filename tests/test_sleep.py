@@ -0,0 +1,133 @@
+import ctypes
+import random
+import select
+import time
+import unittest
+
+from async_python_ffi import ErrorCode, RustCallback, RustClock, RustCompletion
+from ffi import CLOCKS, FfiTestMixin, LIB, last_error_message
+
+
+class SleepTest(FfiTestMixin, unittest.TestCase):
+    def test_concurrent_sleeps_never_complete_early(self):
+        fd = LIB.rust_cq_fd()
+        buf = (RustCompletion * 64)()
+        deadlines = {}
+        for _ in range(500):
+            delay_ms = random.randint(0, 300)
+            deadline = time.monotonic() + delay_ms / 1000
+            op = self.start(LIB.rust_sleep_submit(None, delay_ms))
+            deadlines[LIB.rust_op_id(op)] = deadline
+
+        while deadlines:
+            select.select([fd], [], [], 5)
+            n = LIB.rust_cq_drain(buf, len(buf))
+            now = time.monotonic()
+            for record in buf[:n]:
+                deadline = deadlines.pop(record.op_id, None)
+                if deadline is None:
+                    continue
+                self.assertEqual(record.status, 0)
+                self.assertGreaterEqual(now, deadline)
+
+    def test_cancelled_sleeps_complete_right_away(self):
+        ops = [self.start(LIB.rust_sleep_start(None, 60_000)) for _ in range(100)]
+        start = time.monotonic()
+        for op in ops:
+            LIB.rust_op_cancel(op)
+        for op in ops:
+            self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+        self.assertLess(time.monotonic() - start, 1)
+
+    def test_sleep_fd_becomes_readable_after_delay(self):
+        start = time.monotonic()
+        fd = LIB.rust_sleep_fd(100)
+        self.assertGreaterEqual(fd, 0)
+        try:
+            self.assertEqual(select.select([fd], [], [], 0)[0], [])
+            self.assertEqual(select.select([fd], [], [], 5)[0], [fd])
+            self.assertGreaterEqual(time.monotonic() - start, 0.1)
+        finally:
+            self.assertEqual(LIB.rust_sleep_fd_close(fd), 0)
+
+    def test_sleep_fd_with_zero_delay_is_readable_right_away(self):
+        fd = LIB.rust_sleep_fd(0)
+        try:
+            self.assertEqual(select.select([fd], [], [], 1)[0], [fd])
+        finally:
+            self.assertEqual(LIB.rust_sleep_fd_close(fd), 0)
+
+    def test_sleep_fd_rejects_negative_delay(self):
+        self.assertEqual(LIB.rust_sleep_fd(-1), -1)
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_ARGUMENT)
+        self.assertIn("negative", last_error_message())
+
+    def test_sleep_callback_rejects_null_callback(self):
+        # ctypes only passes null function pointers created without a function
+        self.assertIsNone(LIB.rust_sleep_callback(None, 0, RustCallback(), None))
+        self.assertEqual(LIB.rust_last_error_code(), ErrorCode.INVALID_ARGUMENT)
+        self.assertIn("callback", last_error_message())
+
+    def test_sleep_fd_close_rejects_negative_fd(self):
+        self.assertEqual(LIB.rust_sleep_fd_close(-1), ErrorCode.INVALID_ARGUMENT)
+
+    def test_sleep_until_reaches_deadline_on_every_clock(self):
+        for clock, clock_id in CLOCKS.items():
+            with self.subTest(clock=clock):
+                deadline = time.clock_gettime_ns(clock_id) + 50_000_000
+                self.assertEqual(LIB.rust_sleep_until(deadline, clock), 0)
+                self.assertGreaterEqual(time.clock_gettime_ns(clock_id), deadline)
+
+                deadline = time.clock_gettime_ns(clock_id) + 50_000_000
+                op = self.start(LIB.rust_sleep_until_start(None, deadline, clock))
+                self.assertEqual(LIB.rust_op_wait(op), 0)
+                self.assertGreaterEqual(time.clock_gettime_ns(clock_id), deadline)
+
+    def test_sleep_until_past_deadline_returns_right_away(self):
+        start = time.monotonic()
+        for deadline in [-(2**63), -1, 0, time.monotonic_ns()]:
+            self.assertEqual(LIB.rust_sleep_until(deadline, RustClock.MONOTONIC), 0)
+            op = self.start(LIB.rust_sleep_until_start(None, deadline, RustClock.REALTIME))
+            self.assertEqual(LIB.rust_op_wait(op), 0)
+        self.assertLess(time.monotonic() - start, 1)
+
+    def test_sleep_until_far_deadline_can_be_cancelled(self):
+        op = self.start(LIB.rust_sleep_until_start(None, 2**63 - 1, RustClock.REALTIME))
+        self.assertEqual(LIB.rust_op_poll(op), -1)
+        LIB.rust_op_cancel(op)
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.CANCELLED)
+
+    def test_sleep_until_rejects_unknown_clock(self):
+        self.assertEqual(LIB.rust_sleep_until(0, 3), ErrorCode.INVALID_ARGUMENT)
+        self.assertIn("unknown clock", last_error_message())
+
+    def test_interruptible_sleep_reports_remaining_time(self):
+        op = self.start(LIB.rust_sleep_interruptible_start(None, 60_000_000_000))
+        remaining = ctypes.c_int64(-1)
+        time.sleep(0.1)
+        LIB.rust_op_cancel(op)
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.INTERRUPTED)
+        status = LIB.rust_sleep_interruptible_result(op, ctypes.byref(remaining))
+        self.assertEqual(status, ErrorCode.INTERRUPTED)
+        self.assertGreater(remaining.value, 50_000_000_000)
+        self.assertLessEqual(remaining.value, 59_900_000_000)
+
+    def test_interruptible_sleep_that_completes_has_nothing_remaining(self):
+        op = self.start(LIB.rust_sleep_interruptible_start(None, 10_000_000))
+        remaining = ctypes.c_int64(-1)
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+        status = LIB.rust_sleep_interruptible_result(op, ctypes.byref(remaining))
+        self.assertEqual(status, 0)
+        self.assertEqual(remaining.value, 0)
+
+    def test_interruptible_sleep_is_interrupted_by_pool_shutdown(self):
+        op = self.start(LIB.rust_sleep_interruptible_start(None, 60_000_000_000))
+        remaining = ctypes.c_int64(-1)
+        LIB.rust_pool_shutdown()
+        status = LIB.rust_sleep_interruptible_result(op, ctypes.byref(remaining))
+        self.assertEqual(status, ErrorCode.INTERRUPTED)
+        self.assertGreater(remaining.value, 50_000_000_000)
+
+    def test_interruptible_sleep_rejects_negative_delay(self):
+        op = self.start(LIB.rust_sleep_interruptible_start(None, -1))
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.INVALID_ARGUMENT)
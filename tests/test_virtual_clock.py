@@ -0,0 +1,111 @@
+import ctypes
+import threading
+import time
+import unittest
+
+from async_python_ffi import ErrorCode, RustClock
+from ffi import CLOCKS, HAS_TESTING, LIB, FfiTestMixin
+
+SECOND_NS = 1_000_000_000
+
+
+@unittest.skipUnless(HAS_TESTING, "requires building with --features testing")
+class VirtualClockTest(FfiTestMixin, unittest.TestCase):
+    def setUp(self):
+        self.assertEqual(LIB.rust_clock_set_virtual(True), 0)
+        self.addCleanup(LIB.rust_clock_set_virtual, False)
+
+    def assertPending(self, op):
+        # Give the worker pool a chance to complete the operation if it's
+        # wrongly due
+        time.sleep(0.02)
+        self.assertEqual(LIB.rust_op_poll(op), -1)
+
+    def test_clock_only_moves_when_advanced(self):
+        now = LIB.rust_clock_now()
+        time.sleep(0.01)
+        self.assertEqual(LIB.rust_clock_now(), now)
+        self.assertEqual(LIB.rust_clock_advance(5 * SECOND_NS), 0)
+        self.assertEqual(LIB.rust_clock_now(), now + 5 * SECOND_NS)
+
+    def test_sleep_completes_when_clock_reaches_deadline(self):
+        op = self.start(LIB.rust_sleep_start(None, 60_000))
+        LIB.rust_clock_advance(60 * SECOND_NS - 1)
+        self.assertPending(op)
+        LIB.rust_clock_advance(1)
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+
+    def test_many_sleeps_complete_in_order(self):
+        ops = [self.start(LIB.rust_sleep_start(None, delay * 1000)) for delay in range(1, 11)]
+        for i, op in enumerate(ops):
+            LIB.rust_clock_advance(SECOND_NS)
+            self.assertEqual(LIB.rust_op_wait(op), 0)
+            for pending in ops[i + 1:]:
+                self.assertEqual(LIB.rust_op_poll(pending), -1)
+
+    def test_blocking_sleep_follows_virtual_clock(self):
+        statuses = []
+        thread = threading.Thread(target=lambda: statuses.append(LIB.rust_sleep(3_600_000)))
+        thread.start()
+        time.sleep(0.02)
+        self.assertTrue(thread.is_alive())
+        LIB.rust_clock_advance(3600 * SECOND_NS)
+        thread.join(5)
+        self.assertEqual(statuses, [0])
+
+    def test_sleep_until_follows_virtual_clock(self):
+        for clock in RustClock:
+            with self.subTest(clock=clock):
+                # Restart the virtual clock at the real time, so the deadline
+                # is a tiny bit more than an hour away from it
+                LIB.rust_clock_set_virtual(False)
+                LIB.rust_clock_set_virtual(True)
+                deadline = time.clock_gettime_ns(CLOCKS[clock]) + 3600 * SECOND_NS
+                op = self.start(LIB.rust_sleep_until_start(None, deadline, clock))
+                LIB.rust_clock_advance(3599 * SECOND_NS)
+                self.assertPending(op)
+                LIB.rust_clock_advance(2 * SECOND_NS)
+                self.assertEqual(LIB.rust_op_wait(op), 0)
+                self.assertEqual(LIB.rust_sleep_until(deadline, clock), 0)
+
+    def test_interval_ticks_follow_virtual_clock(self):
+        interval = self.interval(SECOND_NS, SECOND_NS)
+        start = LIB.rust_clock_now()
+        for i in range(1, 4):
+            op = self.start(LIB.rust_interval_next(None, interval))
+            self.assertPending(op)
+            LIB.rust_clock_advance(SECOND_NS)
+            tick = ctypes.c_int64()
+            self.assertEqual(LIB.rust_op_wait(op), 0)
+            LIB.rust_interval_next_result(op, ctypes.byref(tick))
+            self.assertEqual(tick.value, start + i * SECOND_NS)
+
+    def test_interrupted_sleep_reports_exact_remaining_time(self):
+        op = self.start(LIB.rust_sleep_interruptible_start(None, 10 * SECOND_NS))
+        LIB.rust_clock_advance(3 * SECOND_NS)
+        LIB.rust_op_cancel(op)
+        remaining = ctypes.c_int64()
+        self.assertEqual(LIB.rust_op_wait(op), ErrorCode.INTERRUPTED)
+        LIB.rust_sleep_interruptible_result(op, ctypes.byref(remaining))
+        self.assertEqual(remaining.value, 7 * SECOND_NS)
+
+    def test_disabling_wakes_pending_sleeps(self):
+        op = self.start(LIB.rust_sleep_start(None, 3_600_000))
+        self.assertPending(op)
+        self.assertEqual(LIB.rust_clock_set_virtual(False), 0)
+        self.assertEqual(LIB.rust_op_wait(op), 0)
+
+    def test_advance_rejects_negative_time(self):
+        self.assertEqual(LIB.rust_clock_advance(-1), ErrorCode.INVALID_ARGUMENT)
+
+
+@unittest.skipUnless(HAS_TESTING, "requires building with --features testing")
+class RealClockTest(unittest.TestCase):
+    def test_advance_requires_virtual_clock(self):
+        self.assertEqual(LIB.rust_clock_advance(1), ErrorCode.INVALID_STATE)
+
+    def test_now_follows_monotonic_clock(self):
+        before = time.monotonic_ns()
+        now = LIB.rust_clock_now()
+        self.assertLessEqual(before, now)
+        self.assertLessEqual(now, time.monotonic_ns())